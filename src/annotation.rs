@@ -0,0 +1,69 @@
+//! Protocol-specific data attached to transactions by third-party crates.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::BitcoinTransaction;
+
+/// Data a protocol (colored coins, asset tags, ...) can discover in a
+/// transaction. `PROTOCOL` is the key it is stored under in `TxAnnotations`,
+/// so it must be unique per protocol.
+pub trait TxAnnotation: Serialize + DeserializeOwned {
+    const PROTOCOL: &'static str;
+
+    fn detect(tx: &BitcoinTransaction) -> Option<Self>;
+}
+
+/// Annotations for a single transaction, keyed by protocol and stored as JSON
+/// so the whole set serializes without knowing the concrete types.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TxAnnotations {
+    entries: BTreeMap<String, serde_json::Value>,
+}
+
+impl TxAnnotations {
+    pub fn new() -> Self {
+        TxAnnotations::default()
+    }
+
+    pub fn insert<A: TxAnnotation>(&mut self, annotation: &A) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_value(annotation)?;
+        self.entries.insert(A::PROTOCOL.to_string(), value);
+        Ok(())
+    }
+
+    pub fn get<A: TxAnnotation>(&self) -> Result<Option<A>, serde_json::Error> {
+        match self.entries.get(A::PROTOCOL) {
+            Some(value) => serde_json::from_value(value.clone()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove<A: TxAnnotation>(&mut self) -> bool {
+        self.entries.remove(A::PROTOCOL).is_some()
+    }
+
+    /// Runs `A::detect` on `tx` and stores the result, returning whether
+    /// anything was found.
+    pub fn detect<A: TxAnnotation>(
+        &mut self,
+        tx: &BitcoinTransaction,
+    ) -> Result<bool, serde_json::Error> {
+        match A::detect(tx) {
+            Some(annotation) => {
+                self.insert(&annotation)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn protocols(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
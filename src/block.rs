@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+use crate::encode::{self, Decodable, DecodeLimits, DecodeTrace, Encodable};
+use crate::{BitcoinError, BitcoinTransaction, CompactSize, serde_utils};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Serialize, Deserialize)]
+pub struct BlockHash(#[serde(with = "serde_utils::hash_hex")] pub [u8; 32]);
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_blockhash: BlockHash,
+    #[serde(with = "serde_utils::hash_hex")]
+    pub merkle_root: [u8; 32],
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    pub const SIZE: usize = 80;
+
+    pub fn new(
+        version: u32,
+        prev_blockhash: BlockHash,
+        merkle_root: [u8; 32],
+        time: u32,
+        bits: u32,
+        nonce: u32,
+    ) -> Self {
+        BlockHeader {
+            version,
+            prev_blockhash,
+            merkle_root,
+            time,
+            bits,
+            nonce,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+
+    /// Double-SHA256 of the 80-byte header.
+    #[cfg(feature = "sha2")]
+    pub fn block_hash(&self) -> BlockHash {
+        BlockHash(crate::hash::sha256d(&self.to_bytes()))
+    }
+}
+
+impl Encodable for BlockHeader {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Serialize: version, prev_blockhash, merkle_root, time, bits, nonce
+        // (integers 4 bytes LE, hashes 32 bytes as stored)
+        let mut len = self.version.consensus_encode(writer)?;
+        len += self.prev_blockhash.0.consensus_encode(writer)?;
+        len += self.merkle_root.consensus_encode(writer)?;
+        len += self.time.consensus_encode(writer)?;
+        len += self.bits.consensus_encode(writer)?;
+        len += self.nonce.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for BlockHeader {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let header = BlockHeader {
+            version: trace.decode("version", reader, limits)?.0,
+            prev_blockhash: BlockHash(trace.decode("prev_blockhash", reader, limits)?.0),
+            merkle_root: trace.decode("merkle_root", reader, limits)?.0,
+            time: trace.decode("time", reader, limits)?.0,
+            bits: trace.decode("bits", reader, limits)?.0,
+            nonce: trace.decode("nonce", reader, limits)?.0,
+        };
+        Ok((header, Self::SIZE))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub txdata: Vec<BitcoinTransaction>,
+}
+
+impl Block {
+    pub fn new(header: BlockHeader, txdata: Vec<BitcoinTransaction>) -> Self {
+        Block { header, txdata }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+
+    /// Like `from_bytes`, rejecting input that exceeds `limits`.
+    pub fn from_bytes_with_limits(
+        bytes: &[u8],
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial_with_limits(bytes, limits)
+    }
+
+    /// Like `from_bytes`, but accepts non-minimal CompactSize counts and
+    /// lengths. The flag is whether every one of them was minimal.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> Result<(Self, usize, bool), BitcoinError> {
+        encode::deserialize_lenient(bytes)
+    }
+
+    /// Whether the header's merkle root matches the transactions in the block,
+    /// rejecting transaction lists mutated to share another list's root.
+    #[cfg(feature = "sha2")]
+    pub fn check_merkle_root(&self) -> bool {
+        let txids: Vec<_> = self.txdata.iter().map(BitcoinTransaction::txid).collect();
+        match crate::merkle::merkle_root_mutated(&txids) {
+            Some((root, mutated)) => root == self.header.merkle_root && !mutated,
+            None => false,
+        }
+    }
+}
+
+impl Encodable for Block {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Serialize: header (80 bytes) + CompactSize (number of transactions)
+        // + each transaction serialized
+        Ok(self.header.consensus_encode(writer)? + encode::encode_list(&self.txdata, writer)?)
+    }
+}
+
+impl Decodable for Block {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let (header, header_bytes) = trace.decode("header", reader, limits)?;
+
+        let (tx_count, tx_count_bytes) =
+            trace.decode::<CompactSize, _>("tx_count", reader, limits)?;
+
+        let (txdata, txdata_bytes) = trace.field("txdata", |trace| {
+            encode::decode_items(reader, tx_count.value, limits, trace)
+        })?;
+
+        Ok((
+            Block { header, txdata },
+            header_bytes + tx_count_bytes + txdata_bytes,
+        ))
+    }
+}
@@ -0,0 +1,68 @@
+//! Deterministic representations of transactions for content-addressed
+//! storage.
+
+use crate::opcodes::{self, Instruction};
+use crate::{BitcoinTransaction, Script, TransactionInput, Witness};
+
+/// Which normalizations `BitcoinTransaction::canonicalize` applies.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CanonicalForm {
+    /// Drop every witness, leaving the data the txid commits to.
+    pub strip_witness: bool,
+    /// Re-encode scriptSig pushes minimally, as Core's standardness policy
+    /// requires. A scriptSig that doesn't parse is left as it is. Output
+    /// scripts are never touched, since spends commit to them byte for byte.
+    pub minimal_pushes: bool,
+}
+
+impl Default for CanonicalForm {
+    fn default() -> Self {
+        CanonicalForm {
+            strip_witness: true,
+            minimal_pushes: false,
+        }
+    }
+}
+
+impl BitcoinTransaction {
+    pub fn canonicalize(&self, form: &CanonicalForm) -> BitcoinTransaction {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| {
+                let script_sig = if form.minimal_pushes {
+                    minimal_script(&input.script_sig)
+                } else {
+                    input.script_sig.clone()
+                };
+                let witness = if form.strip_witness {
+                    Witness::default()
+                } else {
+                    input.witness.clone()
+                };
+                TransactionInput::new(input.previous_output.clone(), script_sig, input.sequence)
+                    .with_witness(witness)
+            })
+            .collect();
+
+        BitcoinTransaction::new(self.version, inputs, self.outputs.clone(), self.lock_time)
+    }
+
+    /// JSON of the canonical form. Fields appear in declaration order and
+    /// without whitespace, so equal transactions give equal strings.
+    pub fn to_canonical_json(&self, form: &CanonicalForm) -> String {
+        serde_json::to_string(&self.canonicalize(form)).expect("transactions always serialize")
+    }
+}
+
+fn minimal_script(script: &Script) -> Script {
+    let mut bytes = Vec::with_capacity(script.len());
+    for instruction in script.instructions() {
+        match instruction {
+            Ok(Instruction::PushBytes(data)) => opcodes::push_slice(&mut bytes, data),
+            Ok(Instruction::Op(op)) => bytes.push(op.to_u8()),
+            Err(_) => return script.clone(),
+        }
+    }
+    Script::new(bytes)
+}
@@ -0,0 +1,365 @@
+//! Standard output script templates, following Core's `Solver`.
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::opcodes::{self, Instruction, Instructions, Opcode};
+use crate::{BitcoinError, Script, ScriptBuilder};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum ScriptType {
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    /// Bare m-of-n OP_CHECKMULTISIG.
+    Multisig,
+    /// A witness program with a version or length that has no meaning yet.
+    WitnessUnknown,
+    /// OP_RETURN followed only by pushes.
+    OpReturn,
+    NonStandard,
+}
+
+const OP_0: u8 = 0x00;
+const OP_1: u8 = 0x51;
+const OP_16: u8 = 0x60;
+const OP_RETURN: u8 = 0x6a;
+const OP_DUP: u8 = 0x76;
+const OP_EQUAL: u8 = 0x87;
+const OP_EQUALVERIFY: u8 = 0x88;
+const OP_HASH160: u8 = 0xa9;
+const OP_CHECKSIG: u8 = 0xac;
+
+/// Bytes of OP_RETURN data Core relays by default (80 of the 83-byte
+/// -datacarriersize, after the opcode and push prefix).
+pub const MAX_OP_RETURN_DATA: usize = 80;
+
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+impl Script {
+    pub fn new_p2pkh(pubkey_hash: &[u8; 20]) -> Self {
+        Script::new(
+            [
+                &[OP_DUP, OP_HASH160, 0x14],
+                &pubkey_hash[..],
+                &[OP_EQUALVERIFY, OP_CHECKSIG],
+            ]
+            .concat(),
+        )
+    }
+
+    pub fn new_p2sh(script_hash: &[u8; 20]) -> Self {
+        Script::new([&[OP_HASH160, 0x14], &script_hash[..], &[OP_EQUAL]].concat())
+    }
+
+    pub fn new_p2wpkh(pubkey_hash: &[u8; 20]) -> Self {
+        Script::new([&[OP_0, 0x14], &pubkey_hash[..]].concat())
+    }
+
+    pub fn new_p2wsh(script_hash: &[u8; 32]) -> Self {
+        Script::new([&[OP_0, 0x20], &script_hash[..]].concat())
+    }
+
+    /// Takes the tweaked x-only output key; the crate has no curve
+    /// arithmetic to apply the BIP341 tweak itself.
+    pub fn new_p2tr(output_key: &[u8; 32]) -> Self {
+        Script::new([&[OP_1, 0x20], &output_key[..]].concat())
+    }
+
+    /// OP_RETURN followed by a minimal push of `data`, which may be at most
+    /// `MAX_OP_RETURN_DATA` bytes so the output stays relayable.
+    pub fn new_op_return(data: &[u8]) -> Result<Self, BitcoinError> {
+        Script::new_op_return_with_limit(data, MAX_OP_RETURN_DATA)
+    }
+
+    /// Like `new_op_return`, for nodes configured with a different limit.
+    pub fn new_op_return_with_limit(data: &[u8], max_len: usize) -> Result<Self, BitcoinError> {
+        if data.len() > max_len {
+            return Err(BitcoinError::SizeLimitExceeded);
+        }
+        Ok(ScriptBuilder::new()
+            .push_opcode(Opcode::Return)
+            .push_slice(data)
+            .into_script())
+    }
+
+    /// The data embedded by `new_op_return`: the single push after
+    /// OP_RETURN, with a push the builder shortened to OP_1NEGATE or
+    /// OP_1..OP_16 turned back into its byte.
+    pub fn op_return_data(&self) -> Option<Vec<u8>> {
+        self.op_return_push().map(Cow::into_owned)
+    }
+
+    // Borrows a direct push, so only the shortened forms allocate.
+    fn op_return_push(&self) -> Option<Cow<'_, [u8]>> {
+        let mut instructions = self.instructions();
+        let data = match (
+            instructions.next()?,
+            instructions.next()?,
+            instructions.next(),
+        ) {
+            (Ok(Instruction::Op(Opcode::Return)), Ok(push), None) => match push {
+                Instruction::PushBytes(data) => Cow::Borrowed(data),
+                Instruction::Op(Opcode::PushNum(n)) => Cow::Owned(vec![n.get()]),
+                Instruction::Op(Opcode::PushNegOne) => Cow::Owned(vec![0x81]),
+                Instruction::Op(_) => return None,
+            },
+            _ => return None,
+        };
+        Some(data)
+    }
+
+    /// `OP_m <pubkey>... OP_n OP_CHECKMULTISIG`. Use it bare or as a P2SH
+    /// redeem script. Fails unless 1 <= m <= n <= 16 and every key is a
+    /// 33- or 65-byte public key.
+    pub fn new_multisig<K: AsRef<[u8]>>(m: u8, pubkeys: &[K]) -> Result<Self, BitcoinError> {
+        let n = pubkeys.len();
+        if m == 0 || m as usize > n || n > 16 {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        let mut builder = ScriptBuilder::new().push_int(m as i64);
+        for key in pubkeys {
+            if !is_pubkey(key.as_ref()) {
+                return Err(BitcoinError::InvalidFormat);
+            }
+            builder = builder.push_slice(key.as_ref());
+        }
+        Ok(builder
+            .push_int(n as i64)
+            .push_opcode(Opcode::CheckMultiSig)
+            .into_script())
+    }
+
+    /// The threshold and keys of an m-of-n OP_CHECKMULTISIG script.
+    pub fn parse_multisig(&self) -> Option<(u8, Vec<&[u8]>)> {
+        let mut instructions = self.instructions();
+        let m = match instructions.next()? {
+            Ok(Instruction::Op(Opcode::PushNum(m))) => m.get(),
+            _ => return None,
+        };
+
+        let mut pubkeys = Vec::new();
+        let n = loop {
+            match instructions.next()? {
+                Ok(Instruction::PushBytes(key)) if is_pubkey(key) => pubkeys.push(key),
+                Ok(Instruction::Op(Opcode::PushNum(n))) => break n.get(),
+                _ => return None,
+            }
+        };
+        if n as usize != pubkeys.len() || m > n {
+            return None;
+        }
+
+        match (instructions.next(), instructions.next()) {
+            (Some(Ok(Instruction::Op(Opcode::CheckMultiSig))), None) => Some((m, pubkeys)),
+            _ => None,
+        }
+    }
+
+    /// `<lock_time> OP_CHECKLOCKTIMEVERIFY OP_DROP <pubkey> OP_CHECKSIG`:
+    /// spendable by `pubkey` once the spending transaction's lock_time
+    /// reaches `lock_time` (BIP65).
+    pub fn new_cltv(lock_time: u32, pubkey: &[u8]) -> Result<Self, BitcoinError> {
+        timelock_script(Opcode::CheckLockTimeVerify, lock_time, pubkey)
+    }
+
+    /// `<sequence> OP_CHECKSEQUENCEVERIFY OP_DROP <pubkey> OP_CHECKSIG`:
+    /// spendable by `pubkey` once the input's relative lock, in BIP68
+    /// sequence form, is met (BIP112). Fails if `sequence` has the disable
+    /// flag set, which would leave the output unencumbered.
+    pub fn new_csv(sequence: u32, pubkey: &[u8]) -> Result<Self, BitcoinError> {
+        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Err(BitcoinError::InvalidFormat);
+        }
+        timelock_script(Opcode::CheckSequenceVerify, sequence, pubkey)
+    }
+
+    /// The lock time and key of a script built by `new_cltv`.
+    pub fn parse_cltv(&self) -> Option<(u32, &[u8])> {
+        self.parse_timelock(Opcode::CheckLockTimeVerify)
+    }
+
+    /// The sequence and key of a script built by `new_csv`.
+    pub fn parse_csv(&self) -> Option<(u32, &[u8])> {
+        self.parse_timelock(Opcode::CheckSequenceVerify)
+    }
+
+    fn parse_timelock(&self, verify: Opcode) -> Option<(u32, &[u8])> {
+        let mut instructions = self.instructions();
+        let value = match instructions.next()? {
+            Ok(Instruction::Op(Opcode::PushNum(n))) => n.get() as i64,
+            Ok(Instruction::PushBytes(num)) if num.len() <= 5 => opcodes::read_script_num(num),
+            _ => return None,
+        };
+        let value = u32::try_from(value).ok()?;
+
+        let pubkey = match (
+            instructions.next()?,
+            instructions.next()?,
+            instructions.next()?,
+            instructions.next()?,
+            instructions.next(),
+        ) {
+            (
+                Ok(Instruction::Op(op)),
+                Ok(Instruction::Op(Opcode::Drop)),
+                Ok(Instruction::PushBytes(pubkey)),
+                Ok(Instruction::Op(Opcode::CheckSig)),
+                None,
+            ) if op == verify => pubkey,
+            _ => return None,
+        };
+
+        // Only the minimal encoding counts, so the result round-trips.
+        let rebuilt = timelock_script(verify, value, pubkey).ok()?;
+        (rebuilt == *self).then_some((value, pubkey))
+    }
+
+    pub fn classify(&self) -> ScriptType {
+        if self.is_p2pk() {
+            ScriptType::P2pk
+        } else if self.is_p2pkh() {
+            ScriptType::P2pkh
+        } else if self.is_p2sh() {
+            ScriptType::P2sh
+        } else if self.is_p2wpkh() {
+            ScriptType::P2wpkh
+        } else if self.is_p2wsh() {
+            ScriptType::P2wsh
+        } else if self.is_p2tr() {
+            ScriptType::P2tr
+        } else if self
+            .witness_program()
+            .is_some_and(|(version, _)| version != 0)
+        {
+            ScriptType::WitnessUnknown
+        } else if self.parse_multisig().is_some() {
+            ScriptType::Multisig
+        } else if self.is_op_return() {
+            ScriptType::OpReturn
+        } else {
+            ScriptType::NonStandard
+        }
+    }
+
+    /// <pubkey> OP_CHECKSIG, with a 33- or 65-byte key.
+    pub fn is_p2pk(&self) -> bool {
+        match self.bytes.as_slice() {
+            [len, key @ .., OP_CHECKSIG] => *len as usize == key.len() && is_pubkey(key),
+            _ => false,
+        }
+    }
+
+    /// OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG
+    pub fn is_p2pkh(&self) -> bool {
+        matches!(
+            self.bytes.as_slice(),
+            [OP_DUP, OP_HASH160, 0x14, hash @ .., OP_EQUALVERIFY, OP_CHECKSIG] if hash.len() == 20
+        )
+    }
+
+    /// OP_HASH160 <20 bytes> OP_EQUAL
+    pub fn is_p2sh(&self) -> bool {
+        matches!(
+            self.bytes.as_slice(),
+            [OP_HASH160, 0x14, hash @ .., OP_EQUAL] if hash.len() == 20
+        )
+    }
+
+    pub fn is_p2wpkh(&self) -> bool {
+        matches!(self.witness_program(), Some((0, program)) if program.len() == 20)
+    }
+
+    pub fn is_p2wsh(&self) -> bool {
+        matches!(self.witness_program(), Some((0, program)) if program.len() == 32)
+    }
+
+    pub fn is_p2tr(&self) -> bool {
+        matches!(self.witness_program(), Some((1, program)) if program.len() == 32)
+    }
+
+    pub fn is_op_return(&self) -> bool {
+        match self.bytes.split_first() {
+            Some((&OP_RETURN, rest)) => is_push_only(rest),
+            _ => false,
+        }
+    }
+
+    /// The witness version and program of a segwit output script.
+    pub fn witness_program(&self) -> Option<(u8, &[u8])> {
+        let (&version_op, rest) = self.bytes.split_first()?;
+        let (&len, program) = rest.split_first()?;
+        let version = match version_op {
+            OP_0 => 0,
+            OP_1..=OP_16 => version_op - OP_1 + 1,
+            _ => return None,
+        };
+        if !(2..=40).contains(&program.len()) || len as usize != program.len() {
+            return None;
+        }
+        Some((version, program))
+    }
+
+    /// The key, hash or data a standard script commits to: the public key of
+    /// a P2PK output, the hash or witness program of P2PKH, P2SH and segwit
+    /// outputs, and for an OP_RETURN output the same data as
+    /// `op_return_data`. Only that last case can be owned, when the push was
+    /// shortened to a small-number opcode.
+    pub fn payload(&self) -> Option<Cow<'_, [u8]>> {
+        let bytes = self.bytes.as_slice();
+        let payload = match self.classify() {
+            ScriptType::P2pk => &bytes[1..bytes.len() - 1],
+            ScriptType::P2pkh => &bytes[3..23],
+            ScriptType::P2sh => &bytes[2..22],
+            ScriptType::P2wpkh | ScriptType::P2wsh | ScriptType::P2tr => self.witness_program()?.1,
+            ScriptType::OpReturn => return self.op_return_push(),
+            ScriptType::Multisig | ScriptType::WitnessUnknown | ScriptType::NonStandard => {
+                return None;
+            }
+        };
+        Some(Cow::Borrowed(payload))
+    }
+}
+
+fn timelock_script(verify: Opcode, value: u32, pubkey: &[u8]) -> Result<Script, BitcoinError> {
+    if !is_pubkey(pubkey) {
+        return Err(BitcoinError::InvalidFormat);
+    }
+    Ok(ScriptBuilder::new()
+        .push_int(value as i64)
+        .push_opcode(verify)
+        .push_opcode(Opcode::Drop)
+        .push_slice(pubkey)
+        .push_opcode(Opcode::CheckSig)
+        .into_script())
+}
+
+// A compressed or uncompressed public key, judged by size as Core's
+// CPubKey::ValidSize does.
+fn is_pubkey(key: &[u8]) -> bool {
+    match key.first() {
+        Some(0x02 | 0x03) => key.len() == 33,
+        Some(0x04 | 0x06 | 0x07) => key.len() == 65,
+        _ => false,
+    }
+}
+
+// Whether every instruction is a well-formed push, counting OP_1NEGATE,
+// OP_RESERVED and OP_1 to OP_16 as pushes the way Core's IsPushOnly does.
+fn is_push_only(script: &[u8]) -> bool {
+    Instructions::new(script).all(|instruction| match instruction {
+        Ok(Instruction::PushBytes(_)) => true,
+        Ok(Instruction::Op(op)) => {
+            matches!(
+                op,
+                Opcode::PushNum(_) | Opcode::PushNegOne | Opcode::Reserved
+            )
+        }
+        Err(_) => false,
+    })
+}
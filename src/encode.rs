@@ -0,0 +1,559 @@
+//! Consensus encoding over `io::Write` / `io::Read`, so large structures can
+//! be streamed instead of buffered. The `to_bytes`/`from_bytes` methods on
+//! each type are thin wrappers around these traits.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::{BitcoinError, CompactSize};
+
+pub trait Encodable {
+    /// Writes the consensus encoding, returning the number of bytes written.
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize>;
+}
+
+pub trait Decodable: Sized {
+    /// Reads one value under the default `DecodeLimits`, returning it with
+    /// the number of bytes consumed.
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_with_limits(reader, &DecodeLimits::default())
+    }
+
+    /// Like `consensus_decode`, failing with `SizeLimitExceeded` when a count
+    /// or length in the input exceeds `limits`. Implementations pass
+    /// `limits` on to every nested value that has counts or lengths.
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError>;
+
+    /// Like `consensus_decode_with_limits`, recording in `trace` which
+    /// nested value failed, where it started and how many bytes it needed.
+    /// The default records nothing below `Self`; implementations with
+    /// fields decode each of them through `trace`.
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        _trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_with_limits(reader, limits)
+    }
+}
+
+impl From<io::Error> for BitcoinError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => BitcoinError::InsufficientBytes,
+            kind => BitcoinError::Io(kind),
+        }
+    }
+}
+
+/// Encodes `value` into a fresh buffer.
+pub fn serialize<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value
+        .consensus_encode(&mut bytes)
+        .expect("writing to a Vec can't fail");
+    bytes
+}
+
+/// Decodes a `T` from the start of `bytes` under the default limits,
+/// returning it with the number of bytes consumed.
+pub fn deserialize_partial<T: Decodable>(bytes: &[u8]) -> Result<(T, usize), BitcoinError> {
+    deserialize_partial_with_limits(bytes, &DecodeLimits::default())
+}
+
+/// Like `deserialize_partial`, under caller-supplied limits.
+pub fn deserialize_partial_with_limits<T: Decodable>(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+) -> Result<(T, usize), BitcoinError> {
+    decode_limited(bytes, limits, &mut DecodeTrace::default())
+}
+
+/// Like `deserialize_partial` with `lenient` set, also returning whether
+/// the input was minimally encoded. Encoding is canonical, so it was exactly
+/// when re-encoding the value takes as many bytes as decoding consumed.
+pub(crate) fn deserialize_lenient<T: Decodable + Encodable>(
+    bytes: &[u8],
+) -> Result<(T, usize, bool), BitcoinError> {
+    let limits = DecodeLimits {
+        lenient: true,
+        ..DecodeLimits::default()
+    };
+    let (value, consumed) = deserialize_partial_with_limits::<T>(bytes, &limits)?;
+    let minimal = value
+        .consensus_encode(&mut io::sink())
+        .is_ok_and(|len| len == consumed);
+    Ok((value, consumed, minimal))
+}
+
+/// Like `deserialize_partial`, but a failure says which nested value failed,
+/// where it started and, if the input ran out, how many bytes it needed.
+pub fn deserialize_detailed<T: Decodable>(bytes: &[u8]) -> Result<(T, usize), DecodeError> {
+    let mut trace = DecodeTrace::default();
+    match decode_limited(bytes, &DecodeLimits::default(), &mut trace) {
+        Ok(decoded) => Ok(decoded),
+        Err(error) => {
+            let offset = trace.failed_at.unwrap_or(0);
+            Err(DecodeError {
+                needed: trace
+                    .needed
+                    .filter(|_| error == BitcoinError::InsufficientBytes),
+                error,
+                context: short_type_name::<T>(),
+                path: trace.path(),
+                offset,
+                available: bytes.len() - offset,
+            })
+        }
+    }
+}
+
+// Decodes from at most `max_total_size` bytes of `bytes`.
+fn decode_limited<T: Decodable>(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+    trace: &mut DecodeTrace,
+) -> Result<(T, usize), BitcoinError> {
+    let limited = &bytes[..bytes.len().min(limits.max_total_size)];
+    match T::consensus_decode_traced(&mut SliceReader::new(limited), limits, trace) {
+        // Ran into the limit rather than the end of the input.
+        Err(BitcoinError::InsufficientBytes) if limited.len() < bytes.len() => {
+            Err(BitcoinError::SizeLimitExceeded)
+        }
+        result => result,
+    }
+}
+
+/// Caps on what a single decode may claim, so a hostile length or count
+/// fails early instead of driving allocation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DecodeLimits {
+    /// Entries in any one list: inputs, outputs, witness items, transactions
+    /// or merkle hashes.
+    pub max_items: u64,
+    /// Bytes in any one script or witness item.
+    pub max_script_size: u64,
+    /// Bytes consumed by `deserialize_partial_with_limits` and the
+    /// `from_bytes` methods. Streaming through `consensus_decode_with_limits`
+    /// directly isn't capped.
+    pub max_total_size: usize,
+    /// Accept non-minimal CompactSize encodings, such as `0xFD 0x01 0x00`
+    /// for 1, wherever a count or length is read.
+    pub lenient: bool,
+}
+
+// Core's MAX_BLOCK_SERIALIZED_SIZE; no valid structure is bigger.
+const MAX_BLOCK_SERIALIZED_SIZE: usize = 4_000_000;
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_items: MAX_BLOCK_SERIALIZED_SIZE as u64,
+            max_script_size: MAX_BLOCK_SERIALIZED_SIZE as u64,
+            max_total_size: MAX_BLOCK_SERIALIZED_SIZE,
+            lenient: false,
+        }
+    }
+}
+
+pub(crate) fn check_item_count(count: u64, limits: &DecodeLimits) -> Result<(), BitcoinError> {
+    if count > limits.max_items {
+        return Err(BitcoinError::SizeLimitExceeded);
+    }
+    Ok(())
+}
+
+pub(crate) fn check_script_size(len: u64, limits: &DecodeLimits) -> Result<(), BitcoinError> {
+    if len > limits.max_script_size {
+        return Err(BitcoinError::SizeLimitExceeded);
+    }
+    Ok(())
+}
+
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Records where within a value decoding failed. Each field decoded through
+/// `decode`, `field` or `index` is located by its first byte; when one fails
+/// the innermost keeps its start and every level adds its name on the way
+/// out.
+#[derive(Debug, Default)]
+pub struct DecodeTrace {
+    position: usize,
+    needed: Option<usize>,
+    failed_at: Option<usize>,
+    // Innermost segment first.
+    segments: Vec<PathSegment>,
+}
+
+#[derive(Debug)]
+enum PathSegment {
+    Field(&'static str),
+    Index(u64),
+}
+
+impl DecodeTrace {
+    /// Decodes a `T` as the field `name` of the value being decoded.
+    pub fn decode<T: Decodable, R: Read + ?Sized>(
+        &mut self,
+        name: &'static str,
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(T, usize), BitcoinError> {
+        self.field(name, |trace| {
+            T::consensus_decode_traced(reader, limits, trace)
+        })
+    }
+
+    /// Runs `decode` as the field `name`. It returns the bytes it consumed,
+    /// which is how later fields know where they start.
+    pub fn field<T>(
+        &mut self,
+        name: &'static str,
+        decode: impl FnOnce(&mut Self) -> Result<(T, usize), BitcoinError>,
+    ) -> Result<(T, usize), BitcoinError> {
+        self.step(PathSegment::Field(name), decode)
+    }
+
+    /// Like `field`, for the entry at `index` of a list.
+    pub fn index<T>(
+        &mut self,
+        index: u64,
+        decode: impl FnOnce(&mut Self) -> Result<(T, usize), BitcoinError>,
+    ) -> Result<(T, usize), BitcoinError> {
+        self.step(PathSegment::Index(index), decode)
+    }
+
+    /// Declares how many bytes the value being decoded needs from its
+    /// start, reported if the input ends first.
+    pub fn need(&mut self, len: usize) {
+        self.needed = Some(len);
+    }
+
+    fn step<T>(
+        &mut self,
+        segment: PathSegment,
+        decode: impl FnOnce(&mut Self) -> Result<(T, usize), BitcoinError>,
+    ) -> Result<(T, usize), BitcoinError> {
+        let start = self.position;
+        let outer_need = self.needed.take();
+        match decode(self) {
+            Ok((value, len)) => {
+                self.position = start + len;
+                self.needed = outer_need;
+                Ok((value, len))
+            }
+            Err(err) => {
+                self.failed_at.get_or_insert(start);
+                self.segments.push(segment);
+                Err(err)
+            }
+        }
+    }
+
+    fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in self.segments.iter().rev() {
+            match segment {
+                PathSegment::Field(name) if path.is_empty() => path.push_str(name),
+                PathSegment::Field(name) => {
+                    path.push('.');
+                    path.push_str(name);
+                }
+                PathSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+            }
+        }
+        path
+    }
+}
+
+/// A decode failure located within the input, e.g. a script length inside a
+/// transaction's first input is reported at `inputs[0].script_sig` with the
+/// offset of that script.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DecodeError {
+    pub error: BitcoinError,
+    /// The type passed to `deserialize_detailed`, e.g. `"BitcoinTransaction"`.
+    pub context: &'static str,
+    /// The nested value that failed, e.g. `"inputs[0].script_sig"`. Empty
+    /// when `context` has no fields, or doesn't trace them.
+    pub path: String,
+    /// Where the failing value started.
+    pub offset: usize,
+    /// For `InsufficientBytes`, how many bytes the failing value needed
+    /// from `offset`, where it knows.
+    pub needed: Option<usize>,
+    /// How many bytes of input were left at `offset`.
+    pub available: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to decode {}", self.context)?;
+        if !self.path.is_empty() {
+            write!(f, ".{}", self.path)?;
+        }
+        write!(f, " at byte {}: {}", self.offset, self.error)?;
+        match self.needed {
+            Some(needed) => write!(
+                f,
+                " (needed {} bytes, {} available)",
+                needed, self.available
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// A cursor over a byte slice. Reads either advance past the requested
+/// bytes or fail with `InsufficientBytes` and leave the position alone, so
+/// callers don't have to track offsets themselves.
+#[derive(Debug, Clone)]
+pub struct SliceReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        SliceReader { bytes, position: 0 }
+    }
+
+    /// Number of bytes consumed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The bytes not yet consumed.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining().is_empty()
+    }
+
+    /// Borrows the next `len` bytes from the underlying slice.
+    pub fn read_exact(&mut self, len: usize) -> Result<&'a [u8], BitcoinError> {
+        let remaining = self.remaining();
+        if remaining.len() < len {
+            return Err(BitcoinError::InsufficientBytes);
+        }
+        self.position += len;
+        Ok(&remaining[..len])
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<&'a [u8; N], BitcoinError> {
+        Ok(self.read_exact(N)?.try_into().unwrap())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, BitcoinError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_u32_le(&mut self) -> Result<u32, BitcoinError> {
+        Ok(u32::from_le_bytes(*self.read_array()?))
+    }
+
+    pub fn read_u64_le(&mut self) -> Result<u64, BitcoinError> {
+        Ok(u64::from_le_bytes(*self.read_array()?))
+    }
+
+    pub fn read_compact_size(&mut self) -> Result<u64, BitcoinError> {
+        let (size, _) = self.decode::<CompactSize>()?;
+        Ok(size.value)
+    }
+
+    /// Decodes any `Decodable` at the current position. On error the
+    /// position is restored.
+    pub fn decode<T: Decodable>(&mut self) -> Result<(T, usize), BitcoinError> {
+        let start = self.position;
+        T::consensus_decode(self).inspect_err(|_| self.position = start)
+    }
+}
+
+impl Read for SliceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.remaining().len());
+        buf[..len].copy_from_slice(&self.remaining()[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Encodable for u8 {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(&[*self])?;
+        Ok(1)
+    }
+}
+
+impl Decodable for u8 {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        _limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let mut buf = [0u8; 1];
+        reader.read_exact(&mut buf)?;
+        Ok((buf[0], 1))
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        trace.need(1);
+        Self::consensus_decode_with_limits(reader, limits)
+    }
+}
+
+impl Encodable for u32 {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(&self.to_le_bytes())?;
+        Ok(4)
+    }
+}
+
+impl Decodable for u32 {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        _limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        Ok((u32::from_le_bytes(buf), 4))
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        trace.need(4);
+        Self::consensus_decode_with_limits(reader, limits)
+    }
+}
+
+impl Encodable for u64 {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(&self.to_le_bytes())?;
+        Ok(8)
+    }
+}
+
+impl Decodable for u64 {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        _limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+        Ok((u64::from_le_bytes(buf), 8))
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        trace.need(8);
+        Self::consensus_decode_with_limits(reader, limits)
+    }
+}
+
+impl Encodable for [u8; 32] {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(self)?;
+        Ok(32)
+    }
+}
+
+impl Decodable for [u8; 32] {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        _limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let mut buf = [0u8; 32];
+        reader.read_exact(&mut buf)?;
+        Ok((buf, 32))
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        trace.need(32);
+        Self::consensus_decode_with_limits(reader, limits)
+    }
+}
+
+/// Writes a CompactSize length followed by the raw bytes.
+pub(crate) fn encode_len_prefixed<W: Write + ?Sized>(
+    bytes: &[u8],
+    writer: &mut W,
+) -> io::Result<usize> {
+    let len = CompactSize::new(bytes.len() as u64).consensus_encode(writer)?;
+    writer.write_all(bytes)?;
+    Ok(len + bytes.len())
+}
+
+/// Reads exactly `len` bytes. The buffer grows as data arrives rather than
+/// being allocated up front, so an untrusted length can't force a huge
+/// allocation before the input runs out.
+pub(crate) fn read_bytes<R: Read + ?Sized>(
+    reader: &mut R,
+    len: u64,
+) -> Result<Vec<u8>, BitcoinError> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(BitcoinError::InsufficientBytes);
+    }
+    Ok(bytes)
+}
+
+/// Encodes a CompactSize count followed by each item.
+pub(crate) fn encode_list<T: Encodable, W: Write + ?Sized>(
+    items: &[T],
+    writer: &mut W,
+) -> io::Result<usize> {
+    let mut len = CompactSize::new(items.len() as u64).consensus_encode(writer)?;
+    for item in items {
+        len += item.consensus_encode(writer)?;
+    }
+    Ok(len)
+}
+
+/// Decodes `count` items back to back. The count comes from the input, so
+/// the vector isn't pre-allocated from it.
+pub(crate) fn decode_items<T: Decodable, R: Read + ?Sized>(
+    reader: &mut R,
+    count: u64,
+    limits: &DecodeLimits,
+    trace: &mut DecodeTrace,
+) -> Result<(Vec<T>, usize), BitcoinError> {
+    check_item_count(count, limits)?;
+    let mut items = Vec::new();
+    let mut consumed = 0;
+    for index in 0..count {
+        let (item, item_bytes) = trace.index(index, |trace| {
+            T::consensus_decode_traced(reader, limits, trace)
+        })?;
+        items.push(item);
+        consumed += item_bytes;
+    }
+    Ok((items, consumed))
+}
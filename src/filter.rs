@@ -0,0 +1,215 @@
+//! A small filter language over transactions, so what a stream processor
+//! keeps can live in configuration instead of code:
+//!
+//! ```text
+//! output.value > 100000 && output.type == p2tr || lock_time > 0
+//! ```
+//!
+//! A filter is comparisons joined by `&&` and `||`, with `&&` binding
+//! tighter. Within one `&&` group, every `input.*` comparison must hold for
+//! the same input and every `output.*` comparison for the same output.
+//!
+//! Fields: `version`, `lock_time`, `inputs` and `outputs` (counts),
+//! `input.sequence`, `output.value` and `output.type`. Numbers compare with
+//! `==`, `!=`, `<`, `<=`, `>` and `>=`; `output.type` takes a script type
+//! name such as `p2wpkh` and compares with `==` and `!=` only.
+
+use std::cmp::Ordering;
+
+use crate::{BitcoinError, BitcoinTransaction, ScriptType, TransactionInput, TransactionOutput};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TxFilter {
+    // Alternatives, each a group of comparisons that must all hold.
+    any_of: Vec<Vec<Comparison>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Comparison {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Field {
+    Version,
+    LockTime,
+    InputCount,
+    OutputCount,
+    InputSequence,
+    OutputValue,
+    OutputType,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Value {
+    Number(u64),
+    Type(ScriptType),
+}
+
+impl TxFilter {
+    /// Parses a filter expression. Unknown fields, operators or script
+    /// types, and comparisons missing a side, are `InvalidFormat`.
+    pub fn parse(expr: &str) -> Result<Self, BitcoinError> {
+        let tokens = tokenize(expr)?;
+        let mut any_of = Vec::new();
+        for alternative in tokens.split(|token| *token == "||") {
+            let mut all_of = Vec::new();
+            for comparison in alternative.split(|token| *token == "&&") {
+                all_of.push(parse_comparison(comparison)?);
+            }
+            any_of.push(all_of);
+        }
+        Ok(TxFilter { any_of })
+    }
+
+    pub fn matches(&self, tx: &BitcoinTransaction) -> bool {
+        self.any_of.iter().any(|all_of| group_matches(all_of, tx))
+    }
+}
+
+fn group_matches(all_of: &[Comparison], tx: &BitcoinTransaction) -> bool {
+    let mut input_checks = Vec::new();
+    let mut output_checks = Vec::new();
+    for comparison in all_of {
+        let actual = match comparison.field {
+            Field::Version => tx.version as u64,
+            Field::LockTime => tx.lock_time as u64,
+            Field::InputCount => tx.inputs.len() as u64,
+            Field::OutputCount => tx.outputs.len() as u64,
+            Field::InputSequence => {
+                input_checks.push(comparison);
+                continue;
+            }
+            Field::OutputValue | Field::OutputType => {
+                output_checks.push(comparison);
+                continue;
+            }
+        };
+        if !comparison.holds(Value::Number(actual)) {
+            return false;
+        }
+    }
+
+    let input_ok = |input: &TransactionInput| {
+        input_checks
+            .iter()
+            .all(|comparison| comparison.holds(Value::Number(input.sequence as u64)))
+    };
+    let output_ok = |output: &TransactionOutput| {
+        output_checks.iter().all(|comparison| {
+            let actual = match comparison.field {
+                Field::OutputType => Value::Type(output.script_pubkey.classify()),
+                _ => Value::Number(output.value),
+            };
+            comparison.holds(actual)
+        })
+    };
+    (input_checks.is_empty() || tx.inputs.iter().any(input_ok))
+        && (output_checks.is_empty() || tx.outputs.iter().any(output_ok))
+}
+
+impl Comparison {
+    fn holds(&self, actual: Value) -> bool {
+        let ordering = actual.cmp(&self.value);
+        match self.op {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+// Splits into words and operators; whitespace between them is optional.
+fn tokenize(expr: &str) -> Result<Vec<&str>, BitcoinError> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let is_op = |c: char| "=!<>&|".contains(c);
+
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let end = if is_word(c) {
+            rest.find(|c| !is_word(c))
+        } else if is_op(c) {
+            rest.find(|c| !is_op(c))
+        } else {
+            return Err(BitcoinError::InvalidFormat);
+        };
+        let (token, tail) = rest.split_at(end.unwrap_or(rest.len()));
+        tokens.push(token);
+        rest = tail.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_comparison(tokens: &[&str]) -> Result<Comparison, BitcoinError> {
+    let (field, op, value) = match tokens {
+        [field, op, value] => (*field, *op, *value),
+        _ => return Err(BitcoinError::InvalidFormat),
+    };
+
+    let field = match field {
+        "version" => Field::Version,
+        "lock_time" => Field::LockTime,
+        "inputs" => Field::InputCount,
+        "outputs" => Field::OutputCount,
+        "input.sequence" => Field::InputSequence,
+        "output.value" => Field::OutputValue,
+        "output.type" => Field::OutputType,
+        _ => return Err(BitcoinError::InvalidFormat),
+    };
+
+    let op = match op {
+        "==" => Op::Eq,
+        "!=" => Op::Ne,
+        "<" => Op::Lt,
+        "<=" => Op::Le,
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        _ => return Err(BitcoinError::InvalidFormat),
+    };
+
+    let value = if field == Field::OutputType {
+        if !matches!(op, Op::Eq | Op::Ne) {
+            return Err(BitcoinError::InvalidFormat);
+        }
+        Value::Type(parse_script_type(value)?)
+    } else {
+        match value.parse() {
+            Ok(n) => Value::Number(n),
+            Err(_) => return Err(BitcoinError::InvalidFormat),
+        }
+    };
+
+    Ok(Comparison { field, op, value })
+}
+
+fn parse_script_type(name: &str) -> Result<ScriptType, BitcoinError> {
+    Ok(match name {
+        "p2pk" => ScriptType::P2pk,
+        "p2pkh" => ScriptType::P2pkh,
+        "p2sh" => ScriptType::P2sh,
+        "p2wpkh" => ScriptType::P2wpkh,
+        "p2wsh" => ScriptType::P2wsh,
+        "p2tr" => ScriptType::P2tr,
+        "multisig" => ScriptType::Multisig,
+        "witness_unknown" => ScriptType::WitnessUnknown,
+        "op_return" => ScriptType::OpReturn,
+        "nonstandard" => ScriptType::NonStandard,
+        _ => return Err(BitcoinError::InvalidFormat),
+    })
+}
@@ -0,0 +1,108 @@
+//! Wire-format versioning. `FORMAT_VERSION` changes whenever an encoding
+//! produced by this crate changes; `check_fixtures` re-encodes a frozen set
+//! of values so an embedder can confirm at startup that the build it links
+//! against still produces byte-identical output.
+
+use crate::encode::{self, Decodable, Encodable, SliceReader};
+use crate::{
+    BitcoinTransaction, BlockHash, BlockHeader, CompactSize, OutPoint, Script, TransactionInput,
+    TransactionOutput, Witness,
+};
+
+pub const FORMAT_VERSION: u32 = 1;
+
+pub fn format_version() -> u32 {
+    FORMAT_VERSION
+}
+
+/// Encodes and decodes every golden fixture, returning the name of the
+/// first one whose encoding no longer matches the frozen bytes.
+pub fn check_fixtures() -> Result<(), &'static str> {
+    check("compact_size_u8", CompactSize::new(0xfc), "fc")?;
+    check("compact_size_u16", CompactSize::new(0xfd), "fdfd00")?;
+    check("compact_size_u32", CompactSize::new(0x10000), "fe00000100")?;
+    check(
+        "compact_size_u64",
+        CompactSize::new(u64::MAX),
+        "ffffffffffffffffff",
+    )?;
+    check("outpoint", outpoint(), OUTPOINT)?;
+    check("script", p2pkh(), P2PKH)?;
+    check("witness", witness(), WITNESS)?;
+    check("input", input(), INPUT)?;
+    check("output", output(), OUTPUT)?;
+    check("legacy_transaction", legacy_transaction(), LEGACY_TX)?;
+    check("segwit_transaction", segwit_transaction(), SEGWIT_TX)?;
+    check("block_header", genesis_header(), GENESIS_HEADER)?;
+    Ok(())
+}
+
+fn check<T>(name: &'static str, value: T, hex: &str) -> Result<(), &'static str>
+where
+    T: Encodable + Decodable + PartialEq,
+{
+    let bytes = hex::decode(hex).expect("fixture hex is valid");
+    if encode::serialize(&value) != bytes {
+        return Err(name);
+    }
+    match T::consensus_decode(&mut SliceReader::new(&bytes)) {
+        Ok((decoded, len)) if decoded == value && len == bytes.len() => Ok(()),
+        _ => Err(name),
+    }
+}
+
+// Frozen at FORMAT_VERSION 1. Never edit these to make a test pass; a
+// deliberate encoding change bumps FORMAT_VERSION and adds new fixtures.
+const OUTPOINT: &str = "111111111111111111111111111111111111111111111111111111111111111101000000";
+const P2PKH: &str = "1976a914222222222222222222222222222222222222222288ac";
+const WITNESS: &str = "0247303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303021020202020202020202020202020202020202020202020202020202020202020202";
+const INPUT: &str =
+    "111111111111111111111111111111111111111111111111111111111111111101000000020051feffffff";
+const OUTPUT: &str = "50c30000000000001976a914222222222222222222222222222222222222222288ac";
+const LEGACY_TX: &str = "0100000001111111111111111111111111111111111111111111111111111111111111111101000000020051feffffff0150c30000000000001976a914222222222222222222222222222222222222222288ac00000000";
+const SEGWIT_TX: &str = "0200000000010111111111111111111111111111111111111111111111111111111111111111110100000000fdffffff0168bf0000000000001600143333333333333333333333333333333333333333024730303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030302102020202020202020202020202020202020202020202020202020202020202020200350c00";
+const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+
+fn outpoint() -> OutPoint {
+    OutPoint::new([0x11; 32], 1)
+}
+
+fn p2pkh() -> Script {
+    Script::new_p2pkh(&[0x22; 20])
+}
+
+fn witness() -> Witness {
+    Witness::new(vec![vec![0x30; 71], vec![0x02; 33]])
+}
+
+fn input() -> TransactionInput {
+    TransactionInput::new(outpoint(), Script::new(vec![0x00, 0x51]), 0xffff_fffe)
+}
+
+fn output() -> TransactionOutput {
+    TransactionOutput::new(50_000, p2pkh())
+}
+
+fn legacy_transaction() -> BitcoinTransaction {
+    BitcoinTransaction::new(1, vec![input()], vec![output()], 0)
+}
+
+fn segwit_transaction() -> BitcoinTransaction {
+    let input =
+        TransactionInput::new(outpoint(), Script::new(vec![]), 0xffff_fffd).with_witness(witness());
+    let output = TransactionOutput::new(49_000, Script::new_p2wpkh(&[0x33; 20]));
+    BitcoinTransaction::new(2, vec![input], vec![output], 800_000)
+}
+
+fn genesis_header() -> BlockHeader {
+    let merkle_root =
+        hex::decode("3ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a").unwrap();
+    BlockHeader::new(
+        1,
+        BlockHash([0; 32]),
+        merkle_root.try_into().unwrap(),
+        1_231_006_505,
+        0x1d00_ffff,
+        2_083_236_893,
+    )
+}
@@ -0,0 +1,7 @@
+use sha2::{Digest, Sha256};
+
+// Bitcoin's hash256: SHA256 applied twice.
+pub(crate) fn sha256d(data: &[u8]) -> [u8; 32] {
+    let first = Sha256::digest(data);
+    Sha256::digest(first).into()
+}
@@ -0,0 +1,235 @@
+//! Compact in-memory storage for a chain of block headers.
+//!
+//! Each header's `prev_blockhash` is implied by its parent, version and bits
+//! rarely change, and timestamps move by small amounts, so most headers
+//! shrink from 80 bytes to about 40. Every `CHECKPOINT_INTERVAL`th header is
+//! stored in full so a lookup only has to replay headers since the last
+//! checkpoint, hashing each one to fill in its child's `prev_blockhash`.
+
+use std::io::{self, Read, Write};
+
+use crate::encode::{self, Decodable, DecodeLimits, DecodeTrace, Encodable};
+use crate::{BitcoinError, BlockHeader, CompactSize};
+
+// Bounds a lookup to 31 double-SHA256s, for about 1.3 extra bytes per
+// header of full records.
+const CHECKPOINT_INTERVAL: usize = 32;
+
+// Record flags.
+const FULL: u8 = 0x01;
+const SAME_VERSION: u8 = 0x02;
+const SAME_BITS: u8 = 0x04;
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct HeaderChain {
+    records: Vec<u8>,
+    // Byte offset of each checkpoint record.
+    checkpoints: Vec<usize>,
+    len: usize,
+    tip: Option<BlockHeader>,
+}
+
+impl HeaderChain {
+    pub fn new() -> Self {
+        HeaderChain::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.tip.as_ref()
+    }
+
+    /// Appends `header`, which must build on the current tip. The first
+    /// header may have any parent.
+    pub fn push(&mut self, header: BlockHeader) -> Result<(), BitcoinError> {
+        let builds_on_tip = match &self.tip {
+            Some(tip) => header.prev_blockhash == tip.block_hash(),
+            None => true,
+        };
+        if !builds_on_tip {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        match &self.tip {
+            Some(prev) if !self.len.is_multiple_of(CHECKPOINT_INTERVAL) => {
+                encode_record(&header, prev, &mut self.records)
+            }
+            _ => {
+                self.checkpoints.push(self.records.len());
+                self.records.push(FULL);
+                header.consensus_encode(&mut self.records)
+            }
+        }
+        .expect("writing to a Vec can't fail");
+
+        self.len += 1;
+        self.tip = Some(header);
+        Ok(())
+    }
+
+    /// The header at `height`, counting from the first header pushed. This
+    /// replays up to `CHECKPOINT_INTERVAL - 1` records from the nearest
+    /// checkpoint and hashes each of them.
+    pub fn get(&self, height: usize) -> Option<BlockHeader> {
+        if height >= self.len {
+            return None;
+        }
+        let checkpoint = self.checkpoints[height / CHECKPOINT_INTERVAL];
+        let mut reader = &self.records[checkpoint..];
+
+        let mut header = None;
+        for _ in 0..=height % CHECKPOINT_INTERVAL {
+            // Records were written by push, so they always decode.
+            let (next, _) = decode_record(&mut reader, header.as_ref()).unwrap();
+            header = Some(next);
+        }
+        header
+    }
+
+    /// Size of the compressed records, excluding the checkpoint index.
+    pub fn encoded_len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+}
+
+fn encode_record<W: Write + ?Sized>(
+    header: &BlockHeader,
+    prev: &BlockHeader,
+    writer: &mut W,
+) -> io::Result<usize> {
+    let mut flags = 0;
+    if header.version == prev.version {
+        flags |= SAME_VERSION;
+    }
+    if header.bits == prev.bits {
+        flags |= SAME_BITS;
+    }
+
+    let mut len = flags.consensus_encode(writer)?;
+    if flags & SAME_VERSION == 0 {
+        len += header.version.consensus_encode(writer)?;
+    }
+    // Timestamps can go backwards, so the delta is zigzag-encoded.
+    let delta = header.time as i64 - prev.time as i64;
+    len += CompactSize::new(((delta << 1) ^ (delta >> 63)) as u64).consensus_encode(writer)?;
+    if flags & SAME_BITS == 0 {
+        len += header.bits.consensus_encode(writer)?;
+    }
+    len += header.merkle_root.consensus_encode(writer)?;
+    len += header.nonce.consensus_encode(writer)?;
+    Ok(len)
+}
+
+fn decode_record<R: Read + ?Sized>(
+    reader: &mut R,
+    prev: Option<&BlockHeader>,
+) -> Result<(BlockHeader, usize), BitcoinError> {
+    let (flags, mut len) = u8::consensus_decode(reader)?;
+    if flags & FULL != 0 {
+        let (header, header_bytes) = BlockHeader::consensus_decode(reader)?;
+        return Ok((header, len + header_bytes));
+    }
+    let prev = match prev {
+        Some(prev) => prev,
+        None => return Err(BitcoinError::InvalidFormat),
+    };
+
+    let version = if flags & SAME_VERSION != 0 {
+        prev.version
+    } else {
+        let (version, version_bytes) = u32::consensus_decode(reader)?;
+        len += version_bytes;
+        version
+    };
+
+    let (delta, delta_bytes) = CompactSize::consensus_decode(reader)?;
+    len += delta_bytes;
+    let delta = (delta.value >> 1) as i64 ^ -((delta.value & 1) as i64);
+    // A hostile delta can push the sum out of range in either direction.
+    let time = match (prev.time as i64)
+        .checked_add(delta)
+        .and_then(|time| u32::try_from(time).ok())
+    {
+        Some(time) => time,
+        None => return Err(BitcoinError::InvalidFormat),
+    };
+
+    let bits = if flags & SAME_BITS != 0 {
+        prev.bits
+    } else {
+        let (bits, bits_bytes) = u32::consensus_decode(reader)?;
+        len += bits_bytes;
+        bits
+    };
+
+    let (merkle_root, root_bytes) = <[u8; 32]>::consensus_decode(reader)?;
+    let (nonce, nonce_bytes) = u32::consensus_decode(reader)?;
+    len += root_bytes + nonce_bytes;
+
+    let header = BlockHeader::new(version, prev.block_hash(), merkle_root, time, bits, nonce);
+    Ok((header, len))
+}
+
+impl Encodable for HeaderChain {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Serialize: CompactSize (number of headers) + the records as stored
+        let len = CompactSize::new(self.len as u64).consensus_encode(writer)?;
+        writer.write_all(&self.records)?;
+        Ok(len + self.records.len())
+    }
+}
+
+impl Decodable for HeaderChain {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let (count, count_bytes) = trace.decode::<CompactSize, _>("count", reader, limits)?;
+        encode::check_item_count(count.value, limits)?;
+
+        // Re-pushing each header rebuilds the checkpoint index and checks
+        // that full records link up with the headers before them.
+        let (chain, records_bytes) = trace.field("records", |trace| {
+            let mut chain = HeaderChain::new();
+            let mut len = 0;
+            for index in 0..count.value {
+                let ((), record_bytes) = trace.index(index, |_| {
+                    let (header, record_bytes) = decode_record(reader, chain.tip())?;
+                    // Each push hashes the previous header, so stop a forged
+                    // count from running on past the size the caller allowed.
+                    if count_bytes + len + record_bytes > limits.max_total_size {
+                        return Err(BitcoinError::SizeLimitExceeded);
+                    }
+                    chain.push(header)?;
+                    Ok(((), record_bytes))
+                })?;
+                len += record_bytes;
+            }
+            Ok((chain, len))
+        })?;
+        Ok((chain, count_bytes + records_bytes))
+    }
+}
@@ -0,0 +1,306 @@
+//! JSON Lines output: one compact JSON object per line. Transactions and
+//! blocks are written in the shape of Core's `decoderawtransaction` and
+//! `getblock` (verbosity 2) output, minus fields that need chain context
+//! or address encoding (`address`, `confirmations`, `height`, ...). Items
+//! are written as they arrive, so a stream of blocks decoded from a block
+//! file never has to be held at once.
+//!
+//! `txid`, `hash` and the block `hash` need the `sha2` feature. Script
+//! `asm` comes from `Script::to_asm`, which doesn't decode signature
+//! sighash suffixes the way Core does for scriptSigs.
+
+use serde::ser::SerializeSeq;
+use serde::{Serialize, Serializer};
+use std::io::{self, Write};
+
+use crate::{BitcoinTransaction, Block, Script, ScriptType, serde_utils};
+
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+    lines: usize,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonlWriter { writer, lines: 0 }
+    }
+
+    /// Writes `item` using its own `Serialize` impl.
+    pub fn write<T: Serialize + ?Sized>(&mut self, item: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, item)?;
+        self.writer.write_all(b"\n")?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    pub fn write_transaction(&mut self, tx: &BitcoinTransaction) -> io::Result<()> {
+        self.write(&CoreTransaction(tx))
+    }
+
+    pub fn write_block(&mut self, block: &Block) -> io::Result<()> {
+        self.write(&CoreBlock(block))
+    }
+
+    /// Number of lines written so far.
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Writes each item on its own line, returning the number of lines written.
+/// Wrap transactions and blocks in `CoreTransaction` or `CoreBlock` for
+/// Core's schema.
+pub fn write_jsonl<W, I>(writer: W, items: I) -> io::Result<usize>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let mut jsonl = JsonlWriter::new(writer);
+    for item in items {
+        jsonl.write(&item)?;
+    }
+    jsonl.flush()?;
+    Ok(jsonl.lines())
+}
+
+/// Serializes a transaction the way `decoderawtransaction` shows it.
+#[derive(Debug, Clone, Copy)]
+pub struct CoreTransaction<'a>(pub &'a BitcoinTransaction);
+
+/// Serializes a block the way `getblock <hash> 2` shows it.
+#[derive(Debug, Clone, Copy)]
+pub struct CoreBlock<'a>(pub &'a Block);
+
+impl Serialize for CoreTransaction<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TxJson::new(self.0).serialize(serializer)
+    }
+}
+
+impl Serialize for CoreBlock<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let block = self.0;
+        let header = &block.header;
+        let txs = &block.txdata;
+        let prefix = 80 + compact_size_len(txs.len());
+        let stripped = prefix + txs.iter().map(BitcoinTransaction::base_size).sum::<usize>();
+        let size = prefix
+            + txs
+                .iter()
+                .map(BitcoinTransaction::total_size)
+                .sum::<usize>();
+
+        BlockJson {
+            #[cfg(feature = "sha2")]
+            hash: display_hex(&header.block_hash().0),
+            version: header.version,
+            version_hex: format!("{:08x}", header.version),
+            merkleroot: display_hex(&header.merkle_root),
+            time: header.time,
+            nonce: header.nonce,
+            bits: format!("{:08x}", header.bits),
+            n_tx: txs.len(),
+            previousblockhash: (header.prev_blockhash.0 != [0; 32])
+                .then(|| display_hex(&header.prev_blockhash.0)),
+            strippedsize: stripped,
+            size,
+            weight: stripped * 3 + size,
+            tx: TxList(txs),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+struct BlockJson<'a> {
+    #[cfg(feature = "sha2")]
+    hash: String,
+    version: u32,
+    #[serde(rename = "versionHex")]
+    version_hex: String,
+    merkleroot: String,
+    time: u32,
+    nonce: u32,
+    bits: String,
+    #[serde(rename = "nTx")]
+    n_tx: usize,
+    // Absent for the genesis block, as in Core.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previousblockhash: Option<String>,
+    strippedsize: usize,
+    size: usize,
+    weight: usize,
+    tx: TxList<'a>,
+}
+
+// Builds each transaction's JSON only while it is being written, so a
+// block's output is never all in memory at once.
+struct TxList<'a>(&'a [BitcoinTransaction]);
+
+impl Serialize for TxList<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for tx in self.0 {
+            seq.serialize_element(&TxJson::new(tx))?;
+        }
+        seq.end()
+    }
+}
+
+#[derive(Serialize)]
+struct TxJson {
+    #[cfg(feature = "sha2")]
+    txid: String,
+    #[cfg(feature = "sha2")]
+    hash: String,
+    version: u32,
+    size: usize,
+    vsize: usize,
+    weight: usize,
+    locktime: u32,
+    vin: Vec<VinJson>,
+    vout: Vec<VoutJson>,
+}
+
+impl TxJson {
+    fn new(tx: &BitcoinTransaction) -> Self {
+        let stripped = tx.base_size();
+        let size = tx.total_size();
+        let weight = stripped * 3 + size;
+        let coinbase = tx.inputs.len() == 1
+            && tx.inputs[0].previous_output.txid.0 == [0; 32]
+            && tx.inputs[0].previous_output.vout == u32::MAX;
+
+        TxJson {
+            #[cfg(feature = "sha2")]
+            txid: display_hex(&tx.txid().0),
+            #[cfg(feature = "sha2")]
+            hash: display_hex(&tx.wtxid().0),
+            version: tx.version,
+            size,
+            vsize: weight.div_ceil(4),
+            weight,
+            locktime: tx.lock_time,
+            vin: tx
+                .inputs
+                .iter()
+                .map(|input| {
+                    let (coinbase, txid, vout, script_sig) = if coinbase {
+                        (Some(hex::encode(&input.script_sig.bytes)), None, None, None)
+                    } else {
+                        (
+                            None,
+                            Some(display_hex(&input.previous_output.txid.0)),
+                            Some(input.previous_output.vout),
+                            Some(ScriptJson::new(&input.script_sig)),
+                        )
+                    };
+                    VinJson {
+                        coinbase,
+                        txid,
+                        vout,
+                        script_sig,
+                        txinwitness: input.witness.items.iter().map(hex::encode).collect(),
+                        sequence: input.sequence,
+                    }
+                })
+                .collect(),
+            vout: tx
+                .outputs
+                .iter()
+                .enumerate()
+                .map(|(n, output)| VoutJson {
+                    value: output.value,
+                    n,
+                    script_pubkey: ScriptPubKeyJson {
+                        script: ScriptJson::new(&output.script_pubkey),
+                        kind: core_type_name(output.script_pubkey.classify()),
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VinJson {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coinbase: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    txid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vout: Option<u32>,
+    #[serde(rename = "scriptSig", skip_serializing_if = "Option::is_none")]
+    script_sig: Option<ScriptJson>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    txinwitness: Vec<String>,
+    sequence: u32,
+}
+
+#[derive(Serialize)]
+struct VoutJson {
+    #[serde(with = "serde_utils::sat_amount")]
+    value: u64,
+    n: usize,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: ScriptPubKeyJson,
+}
+
+#[derive(Serialize)]
+struct ScriptJson {
+    asm: String,
+    hex: String,
+}
+
+impl ScriptJson {
+    fn new(script: &Script) -> Self {
+        ScriptJson {
+            asm: script.to_asm(),
+            hex: hex::encode(&script.bytes),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ScriptPubKeyJson {
+    #[serde(flatten)]
+    script: ScriptJson,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+// Core's TxoutType names.
+fn core_type_name(script_type: ScriptType) -> &'static str {
+    match script_type {
+        ScriptType::P2pk => "pubkey",
+        ScriptType::P2pkh => "pubkeyhash",
+        ScriptType::P2sh => "scripthash",
+        ScriptType::P2wpkh => "witness_v0_keyhash",
+        ScriptType::P2wsh => "witness_v0_scripthash",
+        ScriptType::P2tr => "witness_v1_taproot",
+        ScriptType::Multisig => "multisig",
+        ScriptType::WitnessUnknown => "witness_unknown",
+        ScriptType::OpReturn => "nulldata",
+        ScriptType::NonStandard => "nonstandard",
+    }
+}
+
+// Hashes are stored in internal byte order; Core displays them reversed.
+fn display_hex(hash: &[u8; 32]) -> String {
+    let mut hash = *hash;
+    hash.reverse();
+    hex::encode(hash)
+}
+
+fn compact_size_len(n: usize) -> usize {
+    crate::CompactSize::new(n as u64).encoded_len()
+}
@@ -1,8 +1,36 @@
 use serde::de::Error;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::io::{self, Read, Write};
 use std::ops::Deref;
 
+pub mod annotation;
+mod block;
+pub mod canonical;
+mod classify;
+pub mod encode;
+pub mod filter;
+pub mod format;
+#[cfg(feature = "sha2")]
+mod hash;
+#[cfg(feature = "sha2")]
+pub mod headers;
+pub mod jsonl;
+#[doc(hidden)]
+pub mod macros;
+#[cfg(feature = "sha2")]
+pub mod merkle;
+pub mod opcodes;
+pub mod serde_utils;
+pub mod sets;
+pub mod view;
+
+pub use block::{Block, BlockHash, BlockHeader};
+pub use classify::{MAX_OP_RETURN_DATA, ScriptType};
+pub use encode::{Decodable, DecodeError, DecodeLimits, DecodeTrace, Encodable, SliceReader};
+pub use filter::TxFilter;
+pub use opcodes::ScriptBuilder;
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct CompactSize {
     pub value: u64,
@@ -12,8 +40,29 @@ pub struct CompactSize {
 pub enum BitcoinError {
     InsufficientBytes,
     InvalidFormat,
+    NumericOverflow,
+    MerkleRootMismatch,
+    SizeLimitExceeded,
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for BitcoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitcoinError::InsufficientBytes => write!(f, "input ended before the value did"),
+            BitcoinError::InvalidFormat => write!(f, "malformed encoding"),
+            BitcoinError::NumericOverflow => write!(f, "length is too large to represent"),
+            BitcoinError::MerkleRootMismatch => {
+                write!(f, "merkle root doesn't match the block header")
+            }
+            BitcoinError::SizeLimitExceeded => write!(f, "size limit exceeded"),
+            BitcoinError::Io(kind) => write!(f, "I/O error: {}", kind),
+        }
+    }
 }
 
+impl std::error::Error for BitcoinError {}
+
 impl CompactSize {
     pub fn new(value: u64) -> Self {
         // TODO: Construct a CompactSize from a u64 value
@@ -21,7 +70,60 @@ impl CompactSize {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        // TODO: Encode according to Bitcoin's CompactSize format:
+        encode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+
+    /// Like `from_bytes`, but also accepts non-minimal encodings such as
+    /// `0xFD 0x01 0x00` for 1. The flag is whether the encoding was minimal.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> Result<(Self, usize, bool), BitcoinError> {
+        encode::deserialize_lenient(bytes)
+    }
+
+    /// Length of the minimal encoding of this value.
+    pub fn encoded_len(&self) -> usize {
+        match self.value {
+            0x00..=0xFC => 1,
+            0xFD..=0xFFFF => 3,
+            0x10000..=0xFFFFFFFF => 5,
+            _ => 9,
+        }
+    }
+
+    fn decode_any<R: Read + ?Sized>(
+        reader: &mut R,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        trace.need(1);
+        let (prefix, _) = u8::consensus_decode(reader)?;
+        match prefix {
+            0x00..=0xFC => Ok((CompactSize::new(prefix as u64), 1)),
+            0xFD => {
+                trace.need(3);
+                let mut buf = [0u8; 2];
+                reader.read_exact(&mut buf)?;
+                Ok((CompactSize::new(u16::from_le_bytes(buf) as u64), 3))
+            }
+            0xFE => {
+                trace.need(5);
+                let (value, _) = u32::consensus_decode(reader)?;
+                Ok((CompactSize::new(value as u64), 5))
+            }
+            0xFF => {
+                trace.need(9);
+                let (value, _) = u64::consensus_decode(reader)?;
+                Ok((CompactSize::new(value), 9))
+            }
+        }
+    }
+}
+
+impl Encodable for CompactSize {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Encode according to Bitcoin's CompactSize format:
         // [0x00–0xFC] => 1 byte
         // [0xFDxxxx] => 0xFD + u16 (2 bytes)
         // [0xFExxxxxxxx] => 0xFE + u32 (4 bytes)
@@ -29,76 +131,48 @@ impl CompactSize {
 
         let value = self.value;
         match value {
-            0x00..=0xFC => vec![value as u8],
+            0x00..=0xFC => (value as u8).consensus_encode(writer),
             0xFD..=0xFFFF => {
-                let mut bytes = vec![0xFD];
-                bytes.extend_from_slice(&(value as u16).to_le_bytes());
-                bytes
+                writer.write_all(&[0xFD])?;
+                writer.write_all(&(value as u16).to_le_bytes())?;
+                Ok(3)
             }
             0x10000..=0xFFFFFFFF => {
-                let mut bytes = vec![0xFE];
-                bytes.extend_from_slice(&(value as u32).to_le_bytes());
-                bytes
+                writer.write_all(&[0xFE])?;
+                Ok(1 + (value as u32).consensus_encode(writer)?)
             }
             _ => {
-                let mut bytes = vec![0xFF];
-                bytes.extend_from_slice(&(value).to_le_bytes());
-                bytes
+                writer.write_all(&[0xFF])?;
+                Ok(1 + value.consensus_encode(writer)?)
             }
         }
     }
+}
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
-        // TODO: Decode CompactSize, returning value and number of bytes consumed.
-        // First check if bytes is empty.
-        // Check that enough bytes are available based on prefix.
+impl Decodable for CompactSize {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
 
-        if bytes.is_empty() {
-            return Err(BitcoinError::InsufficientBytes);
-        }
-        match bytes[0] {
-            0x00..=0xFC => {
-                let value = bytes[0] as u64;
-                Ok((CompactSize::new(value), 1))
-            }
-            0xFD => {
-                if bytes.len() < 3 {
-                    return Err(BitcoinError::InsufficientBytes);
-                }
-
-                let value = u16::from_le_bytes([bytes[1], bytes[2]]) as u64;
-                if value < 0xFD {
-                    return Err(BitcoinError::InvalidFormat);
-                }
-                Ok((CompactSize::new(value), 3))
-            }
-            0xFE => {
-                if bytes.len() < 5 {
-                    return Err(BitcoinError::InsufficientBytes);
-                }
-                let value = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as u64;
-                if value <= 0xFFFF {
-                    return Err(BitcoinError::InvalidFormat);
-                }
-                Ok((CompactSize::new(value), 5))
-            }
-            0xFF => {
-                if bytes.len() < 9 {
-                    return Err(BitcoinError::InsufficientBytes);
-                }
-                let value = u64::from_le_bytes([
-                    bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7], bytes[8],
-                ]);
-                if value <= 0xFFFFFFFF {
-                    return Err(BitcoinError::InvalidFormat);
-                }
-                Ok((CompactSize::new(value), 9))
-            }
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        // Decode CompactSize, returning value and number of bytes consumed.
+        // Non-minimal encodings are rejected unless the limits allow them.
+        let (size, len) = CompactSize::decode_any(reader, trace)?;
+        if len != size.encoded_len() && !limits.lenient {
+            return Err(BitcoinError::InvalidFormat);
         }
+        Ok((size, len))
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct Txid(pub [u8; 32]);
 
 impl Serialize for Txid {
@@ -136,7 +210,31 @@ impl<'de> Deserialize<'de> for Txid {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+impl Encodable for Txid {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.0.consensus_encode(writer)
+    }
+}
+
+impl Decodable for Txid {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let (bytes, consumed) = <[u8; 32]>::consensus_decode_traced(reader, limits, trace)?;
+        Ok((Txid(bytes), consumed))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Serialize, Deserialize)]
 pub struct OutPoint {
     pub txid: Txid,
     pub vout: u32,
@@ -152,23 +250,36 @@ impl OutPoint {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        // TODO: Serialize as: txid (32 bytes) + vout (4 bytes, little-endian)
-        let mut bytes = Vec::new();
-        bytes.extend_from_slice(&self.txid.0);
-        bytes.extend_from_slice(&self.vout.to_le_bytes());
-        bytes
+        encode::serialize(self)
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
-        // TODO: Deserialize 36 bytes: txid[0..32], vout[32..36]
-        // Return error if insufficient bytes
-        if bytes.len() < 36 {
-            return Err(BitcoinError::InsufficientBytes);
-        }
+        encode::deserialize_partial(bytes)
+    }
+}
 
-        let txid = Txid(bytes[0..32].try_into().unwrap());
-        let vout = u32::from_le_bytes(bytes[32..36].try_into().unwrap());
+impl Encodable for OutPoint {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Serialize as: txid (32 bytes) + vout (4 bytes, little-endian)
+        Ok(self.txid.consensus_encode(writer)? + self.vout.consensus_encode(writer)?)
+    }
+}
 
+impl Decodable for OutPoint {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let (txid, _) = trace.decode("txid", reader, limits)?;
+        let (vout, _) = trace.decode("vout", reader, limits)?;
         Ok((OutPoint { txid, vout }, 36))
     }
 }
@@ -185,42 +296,70 @@ impl Script {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        // TODO: Prefix with CompactSize (length), then raw bytes
-        let mut buffer = Vec::new();
+        encode::serialize(self)
+    }
 
-        let compact_size_len = CompactSize::new(self.bytes.len() as u64).to_bytes();
-        buffer.extend(compact_size_len);
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
 
-        buffer.extend(&self.bytes);
+    /// Walk the script as opcodes and data pushes.
+    pub fn instructions(&self) -> opcodes::Instructions<'_> {
+        opcodes::Instructions::new(&self.bytes)
+    }
 
-        buffer
+    /// Disassemble as `bitcoin-cli` shows it, e.g. "OP_DUP OP_HASH160 <hex> ...".
+    pub fn to_asm(&self) -> String {
+        opcodes::to_asm(&self.bytes)
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
-        // TODO: Parse CompactSize prefix, then read that many bytes
-        // Return error if not enough bytes
-        // Read the CompactSize prefix
-        let parse_output = CompactSize::from_bytes(bytes);
-        let (compact_size_len, var_int_len) = match parse_output {
-            Ok((compact_size_value, bytes_count)) => (compact_size_value, bytes_count),
-            Err(_) => return Err(BitcoinError::InsufficientBytes),
-        };
+    /// Parses `bitcoin-cli`-style asm. See `to_asm` for the format; decimal
+    /// words that could also be hex are read as numbers, so not every
+    /// script survives a `to_asm` round trip.
+    pub fn from_asm(asm: &str) -> Result<Self, BitcoinError> {
+        Ok(Script::new(opcodes::from_asm(asm)?))
+    }
+}
 
-        let script_len = compact_size_len.value as usize;
+impl Encodable for Script {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Prefix with CompactSize (length), then raw bytes
+        encode::encode_len_prefixed(&self.bytes, writer)
+    }
+}
 
-        let total_len = var_int_len + script_len;
-        if bytes.len() < total_len {
-            return Err(BitcoinError::InsufficientBytes);
-        }
+impl Decodable for Script {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        // Parse CompactSize prefix, then read that many bytes
+        let (compact_size_len, var_int_len) =
+            CompactSize::consensus_decode_traced(reader, limits, trace)?;
+        // An oversized length is a limit violation however it would overflow.
+        encode::check_script_size(compact_size_len.value, limits)?;
+
+        let script_len = match usize::try_from(compact_size_len.value) {
+            Ok(len) => len,
+            Err(_) => return Err(BitcoinError::NumericOverflow),
+        };
 
-        let script_bytes = bytes[var_int_len..total_len].to_vec();
+        let total_len = match var_int_len.checked_add(script_len) {
+            Some(len) => len,
+            None => return Err(BitcoinError::NumericOverflow),
+        };
+        trace.need(total_len);
 
-        Ok((
-            Script {
-                bytes: script_bytes,
-            },
-            total_len,
-        ))
+        let bytes = encode::read_bytes(reader, compact_size_len.value)?;
+        Ok((Script { bytes }, total_len))
     }
 }
 
@@ -232,11 +371,136 @@ impl Deref for Script {
     }
 }
 
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Witness {
+    pub items: Vec<Vec<u8>>,
+}
+
+impl Witness {
+    pub fn new(items: Vec<Vec<u8>>) -> Self {
+        Witness { items }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+
+    pub fn push(&mut self, item: Vec<u8>) {
+        self.items.push(item);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.items.iter().map(Vec::as_slice)
+    }
+
+    pub fn nth(&self, index: usize) -> Option<&[u8]> {
+        self.items.get(index).map(Vec::as_slice)
+    }
+
+    pub fn last(&self) -> Option<&[u8]> {
+        self.items.last().map(Vec::as_slice)
+    }
+
+    /// The leaf script of a taproot script-path spend: the item before the
+    /// control block, after dropping a BIP341 annex (last item starting with 0x50).
+    pub fn tapscript(&self) -> Option<&[u8]> {
+        let mut items = self.items.as_slice();
+        if items.len() >= 2 && items[items.len() - 1].first() == Some(&0x50) {
+            items = &items[..items.len() - 1];
+        }
+        if items.len() < 2 {
+            return None;
+        }
+        Some(&items[items.len() - 2])
+    }
+}
+
+impl Encodable for Witness {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Serialize: CompactSize (item count) + each item with a CompactSize length
+        let mut len = CompactSize::new(self.items.len() as u64).consensus_encode(writer)?;
+        for item in &self.items {
+            len += encode::encode_len_prefixed(item, writer)?;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for Witness {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let (item_count, item_count_bytes) =
+            trace.decode::<CompactSize, _>("item_count", reader, limits)?;
+
+        // Each item has the same length-prefixed layout as a Script.
+        let (scripts, items_bytes) = trace.field("items", |trace| {
+            encode::decode_items::<Script, _>(reader, item_count.value, limits, trace)
+        })?;
+        let items = scripts.into_iter().map(|script| script.bytes).collect();
+        Ok((Witness { items }, item_count_bytes + items_bytes))
+    }
+}
+
+impl Serialize for Witness {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        // Serialize as a list of hex strings, like Core's `txinwitness`
+        let hex_items: Vec<String> = self.items.iter().map(hex::encode).collect();
+        hex_items.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Witness {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let hex_items = Vec::<String>::deserialize(deserializer)?;
+
+        let mut items = Vec::new();
+        for hex_str in hex_items {
+            match hex::decode(&hex_str) {
+                Ok(bytes) => items.push(bytes),
+                Err(_) => return Err(D::Error::custom("Invalid hex string")),
+            }
+        }
+        Ok(Witness { items })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct TransactionInput {
     pub previous_output: OutPoint,
     pub script_sig: Script,
     pub sequence: u32,
+    // Not part of TransactionInput::to_bytes: the transaction writes it after
+    // the outputs when using the segwit encoding.
+    #[serde(default)]
+    pub witness: Witness,
 }
 
 impl TransactionInput {
@@ -246,59 +510,122 @@ impl TransactionInput {
             previous_output,
             script_sig,
             sequence,
+            witness: Witness::default(),
         }
     }
 
+    pub fn with_witness(mut self, witness: Witness) -> Self {
+        self.witness = witness;
+        self
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
-        // TODO: Serialize: OutPoint + Script (with CompactSize) + sequence (4 bytes LE)
-        let mut bytes = Vec::new();
-        bytes.extend_from_slice(&self.previous_output.to_bytes());
-        bytes.extend_from_slice(&self.script_sig.to_bytes());
-        bytes.extend_from_slice(&self.sequence.to_le_bytes());
-        bytes
+        encode::serialize(self)
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
-        // TODO: Deserialize in order:
+        encode::deserialize_partial(bytes)
+    }
+}
+
+impl Encodable for TransactionInput {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Serialize: OutPoint + Script (with CompactSize) + sequence (4 bytes LE)
+        let mut len = self.previous_output.consensus_encode(writer)?;
+        len += self.script_sig.consensus_encode(writer)?;
+        len += self.sequence.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for TransactionInput {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        // Deserialize in order:
         // - OutPoint (36 bytes)
         // - Script (with CompactSize)
         // - Sequence (4 bytes)
-        if bytes.len() < 41 {
-            return Err(BitcoinError::InsufficientBytes);
-        }
+        let (previous_output, outpoint_bytes) = trace.decode("previous_output", reader, limits)?;
 
-        let txid = Txid(bytes[0..32].try_into().unwrap());
-        let vout = u32::from_le_bytes(bytes[32..36].try_into().unwrap());
-        let previous_output = OutPoint { txid, vout };
+        let (script_sig, script_bytes) = trace.decode("script_sig", reader, limits)?;
 
-        let (script_sig, script_bytes) = match Script::from_bytes(&bytes[36..]) {
-            Ok((script, bytes_used)) => (script, bytes_used),
-            Err(_) => return Err(BitcoinError::InsufficientBytes),
-        };
+        let (sequence, sequence_bytes) = trace.decode("sequence", reader, limits)?;
 
-        let sequence_start = 36 + script_bytes;
+        Ok((
+            TransactionInput {
+                previous_output,
+                script_sig,
+                sequence,
+                witness: Witness::default(),
+            },
+            outpoint_bytes + script_bytes + sequence_bytes,
+        ))
+    }
+}
 
-        if bytes.len() < 4 {
-            return Err(BitcoinError::InsufficientBytes);
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TransactionOutput {
+    pub value: u64,
+    pub script_pubkey: Script,
+}
+
+impl TransactionOutput {
+    pub fn new(value: u64, script_pubkey: Script) -> Self {
+        TransactionOutput {
+            value,
+            script_pubkey,
         }
+    }
 
-        let sequence_bytes = &bytes[sequence_start..sequence_start + 4];
-        let sequence_array = match sequence_bytes.try_into() {
-            Ok(arr) => arr,
-            Err(_) => return Err(BitcoinError::InvalidFormat),
-        };
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
 
-        let sequence = u32::from_le_bytes(sequence_array);
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+}
 
-        let total_bytes = sequence_start + 4;
+impl Encodable for TransactionOutput {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Serialize: value (8 bytes LE) + Script (with CompactSize)
+        Ok(self.value.consensus_encode(writer)? + self.script_pubkey.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for TransactionOutput {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let (value, value_bytes) = trace.decode("value", reader, limits)?;
+
+        let (script_pubkey, script_bytes) = trace.decode("script_pubkey", reader, limits)?;
 
         Ok((
-            TransactionInput {
-                previous_output,
-                script_sig,
-                sequence,
+            TransactionOutput {
+                value,
+                script_pubkey,
             },
-            total_bytes,
+            value_bytes + script_bytes,
         ))
     }
 }
@@ -307,79 +634,210 @@ impl TransactionInput {
 pub struct BitcoinTransaction {
     pub version: u32,
     pub inputs: Vec<TransactionInput>,
+    pub outputs: Vec<TransactionOutput>,
     pub lock_time: u32,
 }
 
 impl BitcoinTransaction {
-    pub fn new(version: u32, inputs: Vec<TransactionInput>, lock_time: u32) -> Self {
+    pub fn new(
+        version: u32,
+        inputs: Vec<TransactionInput>,
+        outputs: Vec<TransactionOutput>,
+        lock_time: u32,
+    ) -> Self {
         // TODO: Construct a transaction from parts
         Self {
             version,
             inputs,
+            outputs,
             lock_time,
         }
     }
 
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// Uses the segwit encoding whenever any input carries witness data.
     pub fn to_bytes(&self) -> Vec<u8> {
-        // TODO: Format:
+        encode::serialize(self)
+    }
+
+    /// Pre-segwit encoding with all witness data dropped.
+    pub fn to_bytes_legacy(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.encode_with(false, &mut bytes)
+            .expect("writing to a Vec can't fail");
+        bytes
+    }
+
+    /// Length of `to_bytes()`, counted without building the encoding.
+    pub fn total_size(&self) -> usize {
+        self.encode_with(self.has_witness(), &mut io::sink())
+            .expect("writing to a sink can't fail")
+    }
+
+    /// Length of `to_bytes_legacy()`, counted without building the encoding.
+    pub fn base_size(&self) -> usize {
+        self.encode_with(false, &mut io::sink())
+            .expect("writing to a sink can't fail")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+
+    /// Like `from_bytes`, rejecting input that exceeds `limits`.
+    pub fn from_bytes_with_limits(
+        bytes: &[u8],
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial_with_limits(bytes, limits)
+    }
+
+    /// Like `from_bytes`, but accepts non-minimal CompactSize counts and
+    /// lengths anywhere in the transaction. The flag is whether every one of
+    /// them was minimal.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> Result<(Self, usize, bool), BitcoinError> {
+        encode::deserialize_lenient(bytes)
+    }
+
+    /// Double-SHA256 of the legacy encoding, so it doesn't commit to witnesses.
+    #[cfg(feature = "sha2")]
+    pub fn txid(&self) -> Txid {
+        Txid(hash::sha256d(&self.to_bytes_legacy()))
+    }
+
+    /// Double-SHA256 of the full encoding; equal to txid() without witnesses.
+    #[cfg(feature = "sha2")]
+    pub fn wtxid(&self) -> Txid {
+        Txid(hash::sha256d(&self.to_bytes()))
+    }
+
+    fn encode_with<W: Write + ?Sized>(&self, segwit: bool, writer: &mut W) -> io::Result<usize> {
+        // Format:
         // - version (4 bytes LE)
+        // - segwit only: marker 0x00, flag 0x01
         // - CompactSize (number of inputs)
         // - each input serialized
+        // - CompactSize (number of outputs)
+        // - each output serialized
+        // - segwit only: each input's witness stack
         // - lock_time (4 bytes LE)
-        let mut bytes = Vec::new();
+        let mut len = self.version.consensus_encode(writer)?;
+        if segwit {
+            writer.write_all(&[0x00, 0x01])?;
+            len += 2;
+        }
 
-        bytes.extend_from_slice(&self.version.to_le_bytes());
+        len += encode::encode_list(&self.inputs, writer)?;
+        len += encode::encode_list(&self.outputs, writer)?;
 
-        let input_count = CompactSize::new(self.inputs.len() as u64).to_bytes();
-        bytes.extend(input_count);
-        for input in &self.inputs {
-            bytes.extend(input.to_bytes());
+        if segwit {
+            for input in &self.inputs {
+                len += input.witness.consensus_encode(writer)?;
+            }
         }
 
-        bytes.extend_from_slice(&self.lock_time.to_le_bytes());
+        len += self.lock_time.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Encodable for BitcoinTransaction {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.encode_with(self.has_witness(), writer)
+    }
+}
 
-        bytes
+impl Decodable for BitcoinTransaction {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
-        // TODO: Read version, CompactSize for input count
-        // Parse inputs one by one
-        // Read final 4 bytes for lock_time
-        if bytes.len() < 8 {
-            return Err(BitcoinError::InsufficientBytes);
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        // Read version, CompactSize for input count
+        // Parse inputs and outputs one by one
+        // Read witnesses if segwit, then the final 4 bytes for lock_time
+        let (version, mut consumed) = trace.decode("version", reader, limits)?;
+
+        let (mut input_count, input_count_bytes) =
+            trace.decode::<CompactSize, _>("input_count", reader, limits)?;
+        consumed += input_count_bytes;
+
+        let mut segwit = false;
+        let mut has_outputs = true;
+        if input_count.value == 0 {
+            // Like Core, a zero input count followed by a non-zero byte is read as
+            // the segwit marker and flag rather than an empty legacy transaction.
+            let (flag, flag_bytes) = trace.decode::<u8, _>("flag", reader, limits)?;
+            consumed += flag_bytes;
+            match flag {
+                // No inputs and no outputs: the byte was the output count.
+                0x00 => has_outputs = false,
+                0x01 => {
+                    segwit = true;
+                    let (count, count_bytes) = trace.decode("input_count", reader, limits)?;
+                    input_count = count;
+                    consumed += count_bytes;
+                }
+                _ => return Err(BitcoinError::InvalidFormat),
+            }
         }
 
-        let version = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
-        let (input_count, input_count_bytes) = match CompactSize::from_bytes(&bytes[4..]) {
-            Ok((count, bytes_used)) => (count, bytes_used),
-            Err(_) => return Err(BitcoinError::InsufficientBytes),
-        };
-        let mut inputs = Vec::new();
-        let mut offset = 4 + input_count_bytes;
+        let (mut inputs, inputs_bytes) = trace.field("inputs", |trace| {
+            encode::decode_items::<TransactionInput, _>(reader, input_count.value, limits, trace)
+        })?;
+        consumed += inputs_bytes;
+
+        let mut outputs = Vec::new();
+        if has_outputs {
+            let (output_count, output_count_bytes) =
+                trace.decode::<CompactSize, _>("output_count", reader, limits)?;
+            let (list, outputs_bytes) = trace.field("outputs", |trace| {
+                encode::decode_items(reader, output_count.value, limits, trace)
+            })?;
+            outputs = list;
+            consumed += output_count_bytes + outputs_bytes;
+        }
 
-        for _ in 0..input_count.value {
-            if offset >= bytes.len() {
-                return Err(BitcoinError::InsufficientBytes);
+        if segwit {
+            let ((), witnesses_bytes) = trace.field("witnesses", |trace| {
+                let mut len = 0;
+                for (index, input) in (0..).zip(&mut inputs) {
+                    let (witness, witness_bytes) = trace.index(index, |trace| {
+                        Witness::consensus_decode_traced(reader, limits, trace)
+                    })?;
+                    input.witness = witness;
+                    len += witness_bytes;
+                }
+                Ok(((), len))
+            })?;
+            consumed += witnesses_bytes;
+            // A segwit encoding without any witness data is non-canonical.
+            if !inputs.iter().any(|input| !input.witness.is_empty()) {
+                return Err(BitcoinError::InvalidFormat);
             }
-            let (input, input_bytes) = match TransactionInput::from_bytes(&bytes[offset..]) {
-                Ok((input, bytes_used)) => (input, bytes_used),
-                Err(_) => return Err(BitcoinError::InsufficientBytes),
-            };
-            inputs.push(input);
-            offset += input_bytes;
         }
-        if bytes.len() < offset + 4 {
-            return Err(BitcoinError::InsufficientBytes);
-        }
-        let lock_time = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
-        let total_bytes = offset + 4;
+
+        let (lock_time, lock_time_bytes) = trace.decode("lock_time", reader, limits)?;
+        consumed += lock_time_bytes;
+
         Ok((
             BitcoinTransaction {
                 version,
                 inputs,
+                outputs,
                 lock_time,
             },
-            total_bytes,
+            consumed,
         ))
     }
 }
@@ -411,6 +869,20 @@ impl fmt::Display for BitcoinTransaction {
             )?;
             writeln!(f, "    Sequence: {}", input.sequence)?;
         }
+        writeln!(f, "  Outputs:")?;
+        for output in &self.outputs {
+            writeln!(f, "    Value: {}", output.value)?;
+            writeln!(
+                f,
+                "    ScriptPubKey Length: {}",
+                output.script_pubkey.bytes.len()
+            )?;
+            writeln!(
+                f,
+                "    ScriptPubKey Bytes: {}",
+                hex::encode(&output.script_pubkey.bytes)
+            )?;
+        }
         writeln!(f, "  Lock Time: {}", self.lock_time)
     }
 }
@@ -0,0 +1,92 @@
+//! Hex literal macros for fixtures and constants. Decoding runs in a `const`
+//! item, so a malformed literal is a compile error rather than a runtime panic.
+
+use crate::{OutPoint, Txid};
+
+/// A `Txid` from 64 hex characters, in the same byte order as its serde form.
+#[macro_export]
+macro_rules! txid {
+    ($hex:expr) => {{
+        const TXID: $crate::Txid = $crate::Txid($crate::macros::decode_hex::<32>($hex));
+        TXID
+    }};
+}
+
+/// A `Script` from hex. The bytes are checked at compile time, but `Script`
+/// owns a `Vec`, so building it still allocates.
+#[macro_export]
+macro_rules! script_hex {
+    ($hex:expr) => {{
+        const BYTES: [u8; $hex.len() / 2] = $crate::macros::decode_hex($hex);
+        $crate::Script::new(BYTES.to_vec())
+    }};
+}
+
+/// An `OutPoint` from `"<txid hex>:<vout>"`.
+#[macro_export]
+macro_rules! outpoint {
+    ($s:expr) => {{
+        const OUTPOINT: $crate::OutPoint = $crate::macros::parse_outpoint($s);
+        OUTPOINT
+    }};
+}
+
+pub const fn decode_hex<const N: usize>(hex: &str) -> [u8; N] {
+    let bytes = hex.as_bytes();
+    if bytes.len() != N * 2 {
+        panic!("hex literal has the wrong length");
+    }
+    decode_hex_at(bytes, 0)
+}
+
+pub const fn parse_outpoint(s: &str) -> OutPoint {
+    let bytes = s.as_bytes();
+    if bytes.len() < 66 || bytes[64] != b':' {
+        panic!("outpoint literal must be <64 hex chars>:<vout>");
+    }
+
+    let txid = decode_hex_at::<32>(bytes, 0);
+
+    let mut vout: u32 = 0;
+    let mut i = 65;
+    while i < bytes.len() {
+        let digit = match bytes[i] {
+            b'0'..=b'9' => (bytes[i] - b'0') as u32,
+            _ => panic!("outpoint vout must be decimal"),
+        };
+        vout = match vout.checked_mul(10) {
+            Some(v) => match v.checked_add(digit) {
+                Some(v) => v,
+                None => panic!("outpoint vout overflows u32"),
+            },
+            None => panic!("outpoint vout overflows u32"),
+        };
+        i += 1;
+    }
+
+    OutPoint {
+        txid: Txid(txid),
+        vout,
+    }
+}
+
+const fn decode_hex_at<const N: usize>(bytes: &[u8], start: usize) -> [u8; N] {
+    let mut out = [0u8; N];
+    let mut i = 0;
+    while i < N {
+        let high = hex_nibble(bytes[start + 2 * i]);
+        let low = hex_nibble(bytes[start + 2 * i + 1]);
+        out[i] = (high << 4) | low;
+        i += 1;
+    }
+    out
+}
+
+const fn hex_nibble(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => panic!("invalid hex character"),
+    }
+}
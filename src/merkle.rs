@@ -0,0 +1,417 @@
+//! Transaction merkle trees, as committed to by `BlockHeader::merkle_root`.
+
+use std::io::{self, Read, Write};
+
+use crate::encode::{self, Decodable, DecodeLimits, DecodeTrace, Encodable};
+use crate::hash::sha256d;
+use crate::{BitcoinError, BlockHeader, CompactSize, Script, Txid};
+
+pub(crate) fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left);
+    data[32..].copy_from_slice(right);
+    sha256d(&data)
+}
+
+/// The merkle root over `txids` in block order, or `None` if there are none.
+/// A level with an odd number of nodes pairs its last node with itself.
+pub fn merkle_root(txids: &[Txid]) -> Option<[u8; 32]> {
+    merkle_root_mutated(txids).map(|(root, _)| root)
+}
+
+/// Like `merkle_root`, also reporting whether any level paired two equal
+/// hashes, as Core's `BlockMerkleRoot` does. Such a list shares its root with
+/// a shorter one (CVE-2012-2459), so a block with a mutated list is invalid
+/// even when the root matches.
+pub fn merkle_root_mutated(txids: &[Txid]) -> Option<([u8; 32], bool)> {
+    if txids.is_empty() {
+        return None;
+    }
+
+    let mut mutated = false;
+    let mut level: Vec<[u8; 32]> = txids.iter().map(|txid| txid.0).collect();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    mutated |= left == right;
+                    hash_pair(left, right)
+                }
+                _ => hash_pair(&pair[0], &pair[0]),
+            })
+            .collect();
+    }
+    Some((level[0], mutated))
+}
+
+/// A merkle branch proving one transaction's inclusion in a block: the
+/// sibling hashes from the leaf up, and the leaf's position in the block.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MerkleProof {
+    pub pos: u32,
+    pub branch: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    /// The proof for `txid` within a block's full txid list, or `None` if it
+    /// isn't in the list.
+    pub fn from_txids(txids: &[Txid], txid: &Txid) -> Option<Self> {
+        let index = txids.iter().position(|t| t == txid)?;
+
+        let mut branch = Vec::new();
+        let mut level: Vec<[u8; 32]> = txids.iter().map(|t| t.0).collect();
+        let mut node = index;
+        while level.len() > 1 {
+            let sibling = (node ^ 1).min(level.len() - 1);
+            branch.push(level[sibling]);
+            level = level
+                .chunks(2)
+                .map(|pair| hash_pair(&pair[0], pair.last().unwrap()))
+                .collect();
+            node /= 2;
+        }
+
+        Some(MerkleProof {
+            pos: index as u32,
+            branch,
+        })
+    }
+
+    /// Whether `pos` fits in a tree of the branch's depth, so the proof
+    /// stands for exactly one position.
+    pub fn is_pos_in_range(&self) -> bool {
+        self.pos.checked_shr(self.branch.len() as u32).unwrap_or(0) == 0
+    }
+
+    /// The merkle root this proof yields for `txid`. Bits of `pos` above the
+    /// branch depth don't affect the result; `verify_tx_inclusion` rejects
+    /// them.
+    pub fn root(&self, txid: &Txid) -> [u8; 32] {
+        let mut hash = txid.0;
+        for (depth, sibling) in self.branch.iter().enumerate() {
+            hash = if (self.pos >> depth) & 1 == 0 {
+                hash_pair(&hash, sibling)
+            } else {
+                hash_pair(sibling, &hash)
+            };
+        }
+        hash
+    }
+
+    /// The `pos` and `merkle` fields of an Electrum
+    /// `blockchain.transaction.get_merkle` response. Electrum shows hashes
+    /// in display order, the reverse of the internal order stored here.
+    pub fn to_electrum(&self) -> (u32, Vec<String>) {
+        let branch = self
+            .branch
+            .iter()
+            .map(|hash| {
+                let mut hash = *hash;
+                hash.reverse();
+                hex::encode(hash)
+            })
+            .collect();
+        (self.pos, branch)
+    }
+
+    pub fn from_electrum<S: AsRef<str>>(pos: u32, branch: &[S]) -> Result<Self, BitcoinError> {
+        let mut hashes = Vec::with_capacity(branch.len());
+        for hash_hex in branch {
+            let mut hash: [u8; 32] = match hex::decode(hash_hex.as_ref()) {
+                Ok(bytes) => match bytes.try_into() {
+                    Ok(hash) => hash,
+                    Err(_) => return Err(BitcoinError::InvalidFormat),
+                },
+                Err(_) => return Err(BitcoinError::InvalidFormat),
+            };
+            hash.reverse();
+            hashes.push(hash);
+        }
+
+        let proof = MerkleProof {
+            pos,
+            branch: hashes,
+        };
+        if !proof.is_pos_in_range() {
+            return Err(BitcoinError::InvalidFormat);
+        }
+        Ok(proof)
+    }
+}
+
+/// Whether `proof` shows `txid` is committed to by `header`'s merkle root.
+pub fn verify_tx_inclusion(txid: &Txid, proof: &MerkleProof, header: &BlockHeader) -> bool {
+    proof.is_pos_in_range() && proof.root(txid) == header.merkle_root
+}
+
+// Max transactions a block can hold: MAX_BLOCK_WEIGHT / MIN_TRANSACTION_WEIGHT.
+const MAX_BLOCK_TRANSACTIONS: u32 = 4_000_000 / 240;
+
+/// A BIP37 partial merkle tree: the subset of a block's merkle tree needed to
+/// prove which transactions matched a filter.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PartialMerkleTree {
+    pub num_transactions: u32,
+    pub bits: Vec<bool>,
+    pub hashes: Vec<[u8; 32]>,
+}
+
+impl PartialMerkleTree {
+    /// Builds the tree proving the txids whose `matches` entry is `true`.
+    /// Fails unless there is exactly one match flag per txid.
+    pub fn from_txids(txids: &[Txid], matches: &[bool]) -> Result<Self, BitcoinError> {
+        if txids.len() != matches.len() {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        let mut tree = PartialMerkleTree {
+            num_transactions: txids.len() as u32,
+            bits: Vec::new(),
+            hashes: Vec::new(),
+        };
+        if !txids.is_empty() {
+            let height = tree.height();
+            tree.build(height, 0, txids, matches);
+        }
+        Ok(tree)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+
+    /// Walks the tree, returning the merkle root it commits to and appending
+    /// the matched txids and their positions in the block.
+    pub fn extract_matches(
+        &self,
+        matches: &mut Vec<Txid>,
+        indexes: &mut Vec<u32>,
+    ) -> Result<[u8; 32], BitcoinError> {
+        if self.num_transactions == 0
+            || self.num_transactions > MAX_BLOCK_TRANSACTIONS
+            || self.hashes.len() > self.num_transactions as usize
+            || self.bits.len() < self.hashes.len()
+        {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        let mut traversal = Traversal {
+            tree: self,
+            bits_used: 0,
+            hashes_used: 0,
+            matches,
+            indexes,
+        };
+        let root = traversal.extract(self.height(), 0)?;
+
+        // Every hash and every flag byte must have been consumed.
+        if traversal.hashes_used != self.hashes.len()
+            || traversal.bits_used.div_ceil(8) != self.bits.len().div_ceil(8)
+        {
+            return Err(BitcoinError::InvalidFormat);
+        }
+        Ok(root)
+    }
+
+    fn width(&self, height: u32) -> u32 {
+        (self.num_transactions + (1 << height) - 1) >> height
+    }
+
+    fn height(&self) -> u32 {
+        let mut height = 0;
+        while self.width(height) > 1 {
+            height += 1;
+        }
+        height
+    }
+
+    fn node_hash(&self, height: u32, pos: u32, txids: &[Txid]) -> [u8; 32] {
+        if height == 0 {
+            return txids[pos as usize].0;
+        }
+        let left = self.node_hash(height - 1, pos * 2, txids);
+        let right = if pos * 2 + 1 < self.width(height - 1) {
+            self.node_hash(height - 1, pos * 2 + 1, txids)
+        } else {
+            left
+        };
+        hash_pair(&left, &right)
+    }
+
+    fn build(&mut self, height: u32, pos: u32, txids: &[Txid], matches: &[bool]) {
+        let start = (pos << height) as usize;
+        let end = (((pos + 1) << height) as usize).min(txids.len());
+        let parent_of_match = matches[start..end].iter().any(|m| *m);
+        self.bits.push(parent_of_match);
+
+        if height == 0 || !parent_of_match {
+            let hash = self.node_hash(height, pos, txids);
+            self.hashes.push(hash);
+        } else {
+            self.build(height - 1, pos * 2, txids, matches);
+            if pos * 2 + 1 < self.width(height - 1) {
+                self.build(height - 1, pos * 2 + 1, txids, matches);
+            }
+        }
+    }
+}
+
+struct Traversal<'a> {
+    tree: &'a PartialMerkleTree,
+    bits_used: usize,
+    hashes_used: usize,
+    matches: &'a mut Vec<Txid>,
+    indexes: &'a mut Vec<u32>,
+}
+
+impl Traversal<'_> {
+    fn extract(&mut self, height: u32, pos: u32) -> Result<[u8; 32], BitcoinError> {
+        let Some(&parent_of_match) = self.tree.bits.get(self.bits_used) else {
+            return Err(BitcoinError::InvalidFormat);
+        };
+        self.bits_used += 1;
+
+        if height == 0 || !parent_of_match {
+            let Some(&hash) = self.tree.hashes.get(self.hashes_used) else {
+                return Err(BitcoinError::InvalidFormat);
+            };
+            self.hashes_used += 1;
+            if height == 0 && parent_of_match {
+                self.matches.push(Txid(hash));
+                self.indexes.push(pos);
+            }
+            return Ok(hash);
+        }
+
+        let left = self.extract(height - 1, pos * 2)?;
+        let right = if pos * 2 + 1 < self.tree.width(height - 1) {
+            let right = self.extract(height - 1, pos * 2 + 1)?;
+            // Identical siblings would let two different trees share a root
+            // (CVE-2012-2459).
+            if right == left {
+                return Err(BitcoinError::InvalidFormat);
+            }
+            right
+        } else {
+            left
+        };
+        Ok(hash_pair(&left, &right))
+    }
+}
+
+impl Encodable for PartialMerkleTree {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        // Serialize: num_transactions (4 bytes LE) + CompactSize (hash count)
+        // + hashes + CompactSize (flag byte count) + flag bits, LSB first
+        let mut flags = vec![0u8; self.bits.len().div_ceil(8)];
+        for (i, bit) in self.bits.iter().enumerate() {
+            flags[i / 8] |= (*bit as u8) << (i % 8);
+        }
+
+        let mut len = self.num_transactions.consensus_encode(writer)?;
+        len += encode::encode_list(&self.hashes, writer)?;
+        len += encode::encode_len_prefixed(&flags, writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for PartialMerkleTree {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let (num_transactions, mut consumed) = trace.decode("num_transactions", reader, limits)?;
+
+        let (hash_count, hash_count_bytes) =
+            trace.decode::<CompactSize, _>("hash_count", reader, limits)?;
+        let (hashes, hashes_bytes) = trace.field("hashes", |trace| {
+            encode::decode_items(reader, hash_count.value, limits, trace)
+        })?;
+        consumed += hash_count_bytes + hashes_bytes;
+
+        // The flag bytes are length-prefixed, the same layout as a Script.
+        let (flags, flags_bytes) = trace.decode::<Script, _>("flags", reader, limits)?;
+        consumed += flags_bytes;
+        let bits = (0..flags.bytes.len() * 8)
+            .map(|i| (flags.bytes[i / 8] >> (i % 8)) & 1 == 1)
+            .collect();
+
+        Ok((
+            PartialMerkleTree {
+                num_transactions,
+                bits,
+                hashes,
+            },
+            consumed,
+        ))
+    }
+}
+
+/// The payload of a BIP37 `merkleblock` message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MerkleBlock {
+    pub header: BlockHeader,
+    pub txn: PartialMerkleTree,
+}
+
+impl MerkleBlock {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), BitcoinError> {
+        encode::deserialize_partial(bytes)
+    }
+
+    /// Like `PartialMerkleTree::extract_matches`, but also checks that the
+    /// tree commits to the header's merkle root.
+    pub fn extract_matches(
+        &self,
+        matches: &mut Vec<Txid>,
+        indexes: &mut Vec<u32>,
+    ) -> Result<(), BitcoinError> {
+        let root = self.txn.extract_matches(matches, indexes)?;
+        if root != self.header.merkle_root {
+            return Err(BitcoinError::MerkleRootMismatch);
+        }
+        Ok(())
+    }
+}
+
+impl Encodable for MerkleBlock {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.header.consensus_encode(writer)? + self.txn.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for MerkleBlock {
+    fn consensus_decode_with_limits<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+    ) -> Result<(Self, usize), BitcoinError> {
+        Self::consensus_decode_traced(reader, limits, &mut DecodeTrace::default())
+    }
+
+    fn consensus_decode_traced<R: Read + ?Sized>(
+        reader: &mut R,
+        limits: &DecodeLimits,
+        trace: &mut DecodeTrace,
+    ) -> Result<(Self, usize), BitcoinError> {
+        let (header, header_bytes) = trace.decode("header", reader, limits)?;
+        let (txn, txn_bytes) = trace.decode("txn", reader, limits)?;
+        Ok((MerkleBlock { header, txn }, header_bytes + txn_bytes))
+    }
+}
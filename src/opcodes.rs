@@ -0,0 +1,420 @@
+//! Script opcodes and an iterator over a script's instructions.
+
+use std::fmt;
+
+use crate::{BitcoinError, Script, SliceReader};
+
+macro_rules! opcodes {
+    ($($variant:ident = $byte:literal, $name:literal;)*) => {
+        #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+        pub enum Opcode {
+            /// OP_0 and OP_PUSHBYTES_1 to OP_PUSHBYTES_75: push the next n
+            /// bytes.
+            PushBytes(PushLen),
+            /// OP_1 to OP_16.
+            PushNum(SmallInt),
+            $($variant,)*
+            /// 0xbb to 0xfe, which have no assigned meaning.
+            Unknown(u8),
+        }
+
+        impl Opcode {
+            pub fn from_u8(byte: u8) -> Self {
+                match byte {
+                    0x00..=0x4b => Opcode::PushBytes(PushLen(byte)),
+                    0x51..=0x60 => Opcode::PushNum(SmallInt(byte - 0x50)),
+                    $($byte => Opcode::$variant,)*
+                    _ => Opcode::Unknown(byte),
+                }
+            }
+
+            pub fn to_u8(self) -> u8 {
+                match self {
+                    Opcode::PushBytes(len) => len.0,
+                    Opcode::PushNum(n) => 0x50 + n.0,
+                    $(Opcode::$variant => $byte,)*
+                    Opcode::Unknown(byte) => byte,
+                }
+            }
+
+            /// Parses a name as printed by `Display`, e.g. `"OP_DUP"`.
+            pub fn from_name(name: &str) -> Option<Self> {
+                $(if name == $name {
+                    return Some(Opcode::$variant);
+                })*
+                match name {
+                    "OP_0" | "OP_FALSE" => return Some(Opcode::PushBytes(PushLen(0))),
+                    "OP_TRUE" => return Some(Opcode::PushNum(SmallInt(1))),
+                    "OP_NOP2" => return Some(Opcode::CheckLockTimeVerify),
+                    "OP_NOP3" => return Some(Opcode::CheckSequenceVerify),
+                    _ => {}
+                }
+                if let Some(len) = name.strip_prefix("OP_PUSHBYTES_") {
+                    return match len.parse() {
+                        Ok(len @ 1..=75) => Opcode::push_bytes(len),
+                        _ => None,
+                    };
+                }
+                Opcode::push_num(name.strip_prefix("OP_")?.parse().ok()?)
+            }
+        }
+
+        impl fmt::Display for Opcode {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    Opcode::PushBytes(PushLen(0)) => write!(f, "OP_0"),
+                    Opcode::PushBytes(len) => write!(f, "OP_PUSHBYTES_{}", len.0),
+                    Opcode::PushNum(n) => write!(f, "OP_{}", n.0),
+                    $(Opcode::$variant => write!(f, $name),)*
+                    Opcode::Unknown(_) => write!(f, "OP_UNKNOWN"),
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    PushData1 = 0x4c, "OP_PUSHDATA1";
+    PushData2 = 0x4d, "OP_PUSHDATA2";
+    PushData4 = 0x4e, "OP_PUSHDATA4";
+    PushNegOne = 0x4f, "OP_1NEGATE";
+    Reserved = 0x50, "OP_RESERVED";
+    Nop = 0x61, "OP_NOP";
+    Ver = 0x62, "OP_VER";
+    If = 0x63, "OP_IF";
+    NotIf = 0x64, "OP_NOTIF";
+    VerIf = 0x65, "OP_VERIF";
+    VerNotIf = 0x66, "OP_VERNOTIF";
+    Else = 0x67, "OP_ELSE";
+    EndIf = 0x68, "OP_ENDIF";
+    Verify = 0x69, "OP_VERIFY";
+    Return = 0x6a, "OP_RETURN";
+    ToAltStack = 0x6b, "OP_TOALTSTACK";
+    FromAltStack = 0x6c, "OP_FROMALTSTACK";
+    TwoDrop = 0x6d, "OP_2DROP";
+    TwoDup = 0x6e, "OP_2DUP";
+    ThreeDup = 0x6f, "OP_3DUP";
+    TwoOver = 0x70, "OP_2OVER";
+    TwoRot = 0x71, "OP_2ROT";
+    TwoSwap = 0x72, "OP_2SWAP";
+    IfDup = 0x73, "OP_IFDUP";
+    Depth = 0x74, "OP_DEPTH";
+    Drop = 0x75, "OP_DROP";
+    Dup = 0x76, "OP_DUP";
+    Nip = 0x77, "OP_NIP";
+    Over = 0x78, "OP_OVER";
+    Pick = 0x79, "OP_PICK";
+    Roll = 0x7a, "OP_ROLL";
+    Rot = 0x7b, "OP_ROT";
+    Swap = 0x7c, "OP_SWAP";
+    Tuck = 0x7d, "OP_TUCK";
+    Cat = 0x7e, "OP_CAT";
+    Substr = 0x7f, "OP_SUBSTR";
+    Left = 0x80, "OP_LEFT";
+    Right = 0x81, "OP_RIGHT";
+    Size = 0x82, "OP_SIZE";
+    Invert = 0x83, "OP_INVERT";
+    And = 0x84, "OP_AND";
+    Or = 0x85, "OP_OR";
+    Xor = 0x86, "OP_XOR";
+    Equal = 0x87, "OP_EQUAL";
+    EqualVerify = 0x88, "OP_EQUALVERIFY";
+    Reserved1 = 0x89, "OP_RESERVED1";
+    Reserved2 = 0x8a, "OP_RESERVED2";
+    OneAdd = 0x8b, "OP_1ADD";
+    OneSub = 0x8c, "OP_1SUB";
+    TwoMul = 0x8d, "OP_2MUL";
+    TwoDiv = 0x8e, "OP_2DIV";
+    Negate = 0x8f, "OP_NEGATE";
+    Abs = 0x90, "OP_ABS";
+    Not = 0x91, "OP_NOT";
+    ZeroNotEqual = 0x92, "OP_0NOTEQUAL";
+    Add = 0x93, "OP_ADD";
+    Sub = 0x94, "OP_SUB";
+    Mul = 0x95, "OP_MUL";
+    Div = 0x96, "OP_DIV";
+    Mod = 0x97, "OP_MOD";
+    LShift = 0x98, "OP_LSHIFT";
+    RShift = 0x99, "OP_RSHIFT";
+    BoolAnd = 0x9a, "OP_BOOLAND";
+    BoolOr = 0x9b, "OP_BOOLOR";
+    NumEqual = 0x9c, "OP_NUMEQUAL";
+    NumEqualVerify = 0x9d, "OP_NUMEQUALVERIFY";
+    NumNotEqual = 0x9e, "OP_NUMNOTEQUAL";
+    LessThan = 0x9f, "OP_LESSTHAN";
+    GreaterThan = 0xa0, "OP_GREATERTHAN";
+    LessThanOrEqual = 0xa1, "OP_LESSTHANOREQUAL";
+    GreaterThanOrEqual = 0xa2, "OP_GREATERTHANOREQUAL";
+    Min = 0xa3, "OP_MIN";
+    Max = 0xa4, "OP_MAX";
+    Within = 0xa5, "OP_WITHIN";
+    Ripemd160 = 0xa6, "OP_RIPEMD160";
+    Sha1 = 0xa7, "OP_SHA1";
+    Sha256 = 0xa8, "OP_SHA256";
+    Hash160 = 0xa9, "OP_HASH160";
+    Hash256 = 0xaa, "OP_HASH256";
+    CodeSeparator = 0xab, "OP_CODESEPARATOR";
+    CheckSig = 0xac, "OP_CHECKSIG";
+    CheckSigVerify = 0xad, "OP_CHECKSIGVERIFY";
+    CheckMultiSig = 0xae, "OP_CHECKMULTISIG";
+    CheckMultiSigVerify = 0xaf, "OP_CHECKMULTISIGVERIFY";
+    Nop1 = 0xb0, "OP_NOP1";
+    CheckLockTimeVerify = 0xb1, "OP_CHECKLOCKTIMEVERIFY";
+    CheckSequenceVerify = 0xb2, "OP_CHECKSEQUENCEVERIFY";
+    Nop4 = 0xb3, "OP_NOP4";
+    Nop5 = 0xb4, "OP_NOP5";
+    Nop6 = 0xb5, "OP_NOP6";
+    Nop7 = 0xb6, "OP_NOP7";
+    Nop8 = 0xb7, "OP_NOP8";
+    Nop9 = 0xb8, "OP_NOP9";
+    Nop10 = 0xb9, "OP_NOP10";
+    CheckSigAdd = 0xba, "OP_CHECKSIGADD";
+    InvalidOpcode = 0xff, "OP_INVALIDOPCODE";
+}
+
+/// The length of a direct push, 0 to 75. Longer pushes need
+/// OP_PUSHDATA1/2/4, so only `Opcode::push_bytes` can make one.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct PushLen(u8);
+
+impl PushLen {
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+/// The value pushed by OP_1 to OP_16; made only by `Opcode::push_num`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct SmallInt(u8);
+
+impl SmallInt {
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl Opcode {
+    /// OP_0 or OP_PUSHBYTES_`len`, or `None` past 75 bytes.
+    pub fn push_bytes(len: u8) -> Option<Self> {
+        (len <= 0x4b).then_some(Opcode::PushBytes(PushLen(len)))
+    }
+
+    /// OP_`n` for `n` from 1 to 16, or `None` otherwise.
+    pub fn push_num(n: u8) -> Option<Self> {
+        (1..=16)
+            .contains(&n)
+            .then_some(Opcode::PushNum(SmallInt(n)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction<'a> {
+    /// Data pushed by OP_0, OP_PUSHBYTES_n or OP_PUSHDATA1/2/4.
+    PushBytes(&'a [u8]),
+    Op(Opcode),
+}
+
+/// Iterator returned by `Script::instructions`. A push that runs past the
+/// end of the script yields `InsufficientBytes` and ends the iteration.
+pub struct Instructions<'a> {
+    reader: SliceReader<'a>,
+    done: bool,
+}
+
+impl<'a> Instructions<'a> {
+    pub(crate) fn new(script: &'a [u8]) -> Self {
+        Instructions {
+            reader: SliceReader::new(script),
+            done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, BitcoinError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let reader = &mut self.reader;
+        let byte = reader.read_u8().ok()?;
+
+        let len = match Opcode::from_u8(byte) {
+            Opcode::PushBytes(len) => Ok(len.get() as usize),
+            Opcode::PushData1 => reader.read_u8().map(usize::from),
+            Opcode::PushData2 => reader
+                .read_array::<2>()
+                .map(|len| u16::from_le_bytes(*len) as usize),
+            Opcode::PushData4 => reader.read_u32_le().map(|len| len as usize),
+            op => return Some(Ok(Instruction::Op(op))),
+        };
+        let push = len.and_then(|len| reader.read_exact(len));
+        self.done = push.is_err();
+        Some(push.map(Instruction::PushBytes))
+    }
+}
+
+/// Builds a script one instruction at a time, always choosing the minimal
+/// push encoding.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ScriptBuilder {
+    bytes: Vec<u8>,
+}
+
+impl ScriptBuilder {
+    pub fn new() -> Self {
+        ScriptBuilder::default()
+    }
+
+    pub fn push_opcode(mut self, op: Opcode) -> Self {
+        self.bytes.push(op.to_u8());
+        self
+    }
+
+    /// Pushes `data` with OP_0, OP_1NEGATE, OP_1 to OP_16, a direct push or
+    /// OP_PUSHDATA1/2/4, whichever is shortest.
+    pub fn push_slice(mut self, data: &[u8]) -> Self {
+        push_slice(&mut self.bytes, data);
+        self
+    }
+
+    /// Pushes `n` as a script number, using OP_0, OP_1NEGATE or OP_1 to
+    /// OP_16 where they apply.
+    pub fn push_int(mut self, n: i64) -> Self {
+        push_int(&mut self.bytes, n);
+        self
+    }
+
+    pub fn into_script(self) -> Script {
+        Script::new(self.bytes)
+    }
+}
+
+/// Appends the minimal push of `data`, as required by Core's MINIMALDATA
+/// rule (BIP62).
+pub(crate) fn push_slice(script: &mut Vec<u8>, data: &[u8]) {
+    match data {
+        [] => script.push(Opcode::PushBytes(PushLen(0)).to_u8()),
+        [n @ 1..=16] => script.push(Opcode::PushNum(SmallInt(*n)).to_u8()),
+        [0x81] => script.push(Opcode::PushNegOne.to_u8()),
+        _ => {
+            let len = data.len();
+            if len <= 0x4b {
+                script.push(len as u8);
+            } else if len <= 0xff {
+                script.push(Opcode::PushData1.to_u8());
+                script.push(len as u8);
+            } else if len <= 0xffff {
+                script.push(Opcode::PushData2.to_u8());
+                script.extend_from_slice(&(len as u16).to_le_bytes());
+            } else {
+                script.push(Opcode::PushData4.to_u8());
+                script.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+            script.extend_from_slice(data);
+        }
+    }
+}
+
+/// Appends `n` as a minimally-encoded script number.
+pub(crate) fn push_int(script: &mut Vec<u8>, n: i64) {
+    match n {
+        -1 => script.push(Opcode::PushNegOne.to_u8()),
+        0 => script.push(Opcode::PushBytes(PushLen(0)).to_u8()),
+        1..=16 => script.push(Opcode::PushNum(SmallInt(n as u8)).to_u8()),
+        _ => push_slice(script, &script_num(n)),
+    }
+}
+
+// Little-endian magnitude with the sign in the top bit of the last byte.
+fn script_num(n: i64) -> Vec<u8> {
+    let mut magnitude = n.unsigned_abs();
+    let mut bytes = Vec::new();
+    while magnitude > 0 {
+        bytes.push(magnitude as u8);
+        magnitude >>= 8;
+    }
+    let sign = if n < 0 { 0x80 } else { 0x00 };
+    match bytes.last_mut() {
+        Some(last) if *last & 0x80 != 0 => bytes.push(sign),
+        Some(last) => *last |= sign,
+        None => {}
+    }
+    bytes
+}
+
+// Reads a script number of up to 5 bytes, not requiring minimal encoding.
+pub(crate) fn read_script_num(bytes: &[u8]) -> i64 {
+    let mut value: i64 = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        value |= (*byte as i64) << (8 * i);
+    }
+    match bytes.last() {
+        Some(last) if last & 0x80 != 0 => -(value & !(0x80 << (8 * (bytes.len() - 1)))),
+        _ => value,
+    }
+}
+
+/// Disassembles a script the way `bitcoin-cli decodescript` does: pushes of
+/// up to 4 bytes as numbers, longer pushes as hex, and `[error]` for a push
+/// that runs past the end.
+pub(crate) fn to_asm(script: &[u8]) -> String {
+    let mut words = Vec::new();
+    for instruction in Instructions::new(script) {
+        let word = match instruction {
+            Ok(Instruction::PushBytes(data)) if data.len() <= 4 => {
+                read_script_num(data).to_string()
+            }
+            Ok(Instruction::PushBytes(data)) => hex::encode(data),
+            Ok(Instruction::Op(Opcode::PushNegOne)) => "-1".to_string(),
+            Ok(Instruction::Op(Opcode::PushNum(n))) => n.get().to_string(),
+            Ok(Instruction::Op(op)) => op.to_string(),
+            Err(_) => "[error]".to_string(),
+        };
+        words.push(word);
+    }
+    words.join(" ")
+}
+
+/// Parses the output of `to_asm`, plus opcode names such as `OP_1` and
+/// `OP_TRUE`. Pushes are re-encoded minimally, so scripts with non-minimal
+/// pushes won't round-trip byte for byte.
+///
+/// The asm format itself is ambiguous: a decimal word of up to 10 digits
+/// without a leading zero and within `i32` range is read as a number, even
+/// if `to_asm` printed it as the hex of a 5-byte push such as
+/// `05 1000000000`. Such scripts don't round-trip.
+pub(crate) fn from_asm(asm: &str) -> Result<Vec<u8>, BitcoinError> {
+    let mut script = Vec::new();
+    for word in asm.split_whitespace() {
+        if let Some(op) = Opcode::from_name(word) {
+            script.push(op.to_u8());
+        } else if let Some(n) = parse_asm_num(word) {
+            push_int(&mut script, n);
+        } else {
+            match hex::decode(word) {
+                Ok(data) => push_slice(&mut script, &data),
+                Err(_) => return Err(BitcoinError::InvalidFormat),
+            }
+        }
+    }
+    Ok(script)
+}
+
+// `to_asm` only prints numbers that fit a 4-byte script number, without
+// leading zeros. Any other all-digit word must be a hex push.
+fn parse_asm_num(word: &str) -> Option<i64> {
+    let digits = word.strip_prefix('-').unwrap_or(word);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if digits.len() > 1 && digits.starts_with('0') {
+        return None;
+    }
+    let n: i64 = word.parse().ok()?;
+    if n.abs() > i32::MAX as i64 {
+        return None;
+    }
+    Some(n)
+}
@@ -0,0 +1,109 @@
+//! Field-level serde adapters for Bitcoin-conventional JSON, for use with
+//! `#[serde(with = "...")]` on your own structs.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::Script;
+
+/// A `u32` as the hex of its little-endian bytes, e.g. a version or sequence
+/// of `1` becomes `"01000000"`.
+pub mod hex_u32 {
+    use super::*;
+
+    pub fn serialize<S>(value: &u32, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&hex::encode(value.to_le_bytes()))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u32, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex_str = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&hex_str).map_err(|_| D::Error::custom("Invalid hex string"))?;
+        let array: [u8; 4] = bytes
+            .try_into()
+            .map_err(|_| D::Error::custom("Hex string must be 4 bytes"))?;
+        Ok(u32::from_le_bytes(array))
+    }
+}
+
+/// A satoshi amount as a BTC-denominated number, the way Core's RPC reports
+/// values (e.g. `150_000_000` becomes `1.5`).
+pub mod sat_amount {
+    use super::*;
+
+    const SATS_PER_BTC: f64 = 100_000_000.0;
+    const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+    pub fn serialize<S>(value: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_f64(*value as f64 / SATS_PER_BTC)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let btc = f64::deserialize(deserializer)?;
+        if !btc.is_finite() || btc < 0.0 {
+            return Err(D::Error::custom("Amount must be a non-negative number"));
+        }
+
+        let sats = (btc * SATS_PER_BTC).round();
+        if sats > MAX_MONEY as f64 {
+            return Err(D::Error::custom("Amount exceeds 21 million BTC"));
+        }
+        Ok(sats as u64)
+    }
+}
+
+/// A `Script` as a bare hex string of its bytes (no length prefix).
+pub mod script_hex {
+    use super::*;
+
+    pub fn serialize<S>(script: &Script, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&hex::encode(&script.bytes))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Script, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex_str = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&hex_str).map_err(|_| D::Error::custom("Invalid hex string"))?;
+        Ok(Script::new(bytes))
+    }
+}
+
+/// A 32-byte hash as a 64-character hex string, in the same byte order as
+/// `Txid` (no reversal).
+pub mod hash_hex {
+    use super::*;
+
+    pub fn serialize<S>(hash: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&hex::encode(hash))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<[u8; 32], D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex_str = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&hex_str).map_err(|_| D::Error::custom("Invalid hex string"))?;
+        bytes
+            .try_into()
+            .map_err(|_| D::Error::custom("Hex string must be 32 bytes"))
+    }
+}
@@ -0,0 +1,121 @@
+//! Sorted, deduplicated sets for fast membership tests over large numbers of
+//! txids or outpoints.
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{OutPoint, Txid};
+
+pub type TxidSet = SortedSet<Txid>;
+pub type OutPointSet = SortedSet<OutPoint>;
+
+/// A set backed by a sorted `Vec`: one allocation, binary-search lookups and
+/// cheap bulk construction via `FromIterator`. Single inserts and removals
+/// are O(n), so prefer building the set in one go.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[serde(transparent)]
+pub struct SortedSet<T> {
+    items: Vec<T>,
+}
+
+impl<T: Ord> SortedSet<T> {
+    pub fn new() -> Self {
+        SortedSet { items: Vec::new() }
+    }
+
+    pub fn contains(&self, item: &T) -> bool {
+        self.items.binary_search(item).is_ok()
+    }
+
+    /// Returns `false` if the item was already present.
+    pub fn insert(&mut self, item: T) -> bool {
+        match self.items.binary_search(&item) {
+            Ok(_) => false,
+            Err(index) => {
+                self.items.insert(index, item);
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, item: &T) -> bool {
+        match self.items.binary_search(item) {
+            Ok(index) => {
+                self.items.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Iterates in ascending order.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<T: Ord> Default for SortedSet<T> {
+    fn default() -> Self {
+        SortedSet::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for SortedSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut items: Vec<T> = iter.into_iter().collect();
+        items.sort_unstable();
+        items.dedup();
+        SortedSet { items }
+    }
+}
+
+impl<T: Ord> Extend<T> for SortedSet<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.items.extend(iter);
+        self.items.sort_unstable();
+        self.items.dedup();
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SortedSet<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<T> IntoIterator for SortedSet<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+impl<'de, T> Deserialize<'de> for SortedSet<T>
+where
+    T: Ord + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Input isn't trusted to be sorted or unique, so rebuild the invariant.
+        let items = Vec::<T>::deserialize(deserializer)?;
+        Ok(items.into_iter().collect())
+    }
+}
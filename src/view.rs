@@ -0,0 +1,345 @@
+//! Borrowed views over serialized data. Parsing validates the encoding and
+//! records where each part lives in the source slice; nothing is copied
+//! until one of the `to_*` methods materializes an owned value.
+
+use crate::{
+    BitcoinError, BitcoinTransaction, OutPoint, Script, SliceReader, TransactionInput,
+    TransactionOutput, Witness,
+};
+
+/// Runs `read` over `bytes`, returning the value with the bytes consumed.
+fn parse_with<'a, T>(
+    bytes: &'a [u8],
+    read: impl FnOnce(&mut SliceReader<'a>) -> Result<T, BitcoinError>,
+) -> Result<(T, usize), BitcoinError> {
+    let mut reader = SliceReader::new(bytes);
+    let value = read(&mut reader)?;
+    Ok((value, reader.position()))
+}
+
+/// Reads `count` items back to back, returning the bytes they span and how
+/// many were read.
+fn read_region<'a>(
+    reader: &mut SliceReader<'a>,
+    count: u64,
+    mut read: impl FnMut(&mut SliceReader<'a>) -> Result<(), BitcoinError>,
+) -> Result<(&'a [u8], usize), BitcoinError> {
+    let region = reader.remaining();
+    let start = reader.position();
+    let mut parsed = 0;
+    for _ in 0..count {
+        read(reader)?;
+        parsed += 1;
+    }
+    Ok((&region[..reader.position() - start], parsed))
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ScriptRef<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ScriptRef<'a> {
+    /// Parses a CompactSize-prefixed script, like `Script::from_bytes`.
+    pub fn parse(bytes: &'a [u8]) -> Result<(Self, usize), BitcoinError> {
+        parse_with(bytes, Self::read_from)
+    }
+
+    pub fn read_from(reader: &mut SliceReader<'a>) -> Result<Self, BitcoinError> {
+        let len = match usize::try_from(reader.read_compact_size()?) {
+            Ok(len) => len,
+            Err(_) => return Err(BitcoinError::NumericOverflow),
+        };
+        Ok(ScriptRef {
+            bytes: reader.read_exact(len)?,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn to_script(&self) -> Script {
+        Script::new(self.bytes.to_vec())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct WitnessRef<'a> {
+    // The encoded items, after the item count.
+    items: &'a [u8],
+    len: usize,
+}
+
+impl<'a> WitnessRef<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<(Self, usize), BitcoinError> {
+        parse_with(bytes, Self::read_from)
+    }
+
+    pub fn read_from(reader: &mut SliceReader<'a>) -> Result<Self, BitcoinError> {
+        let count = reader.read_compact_size()?;
+        let (items, len) = read_region(reader, count, |reader| {
+            ScriptRef::read_from(reader).map(drop)
+        })?;
+        Ok(WitnessRef { items, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> WitnessIter<'a> {
+        WitnessIter {
+            reader: SliceReader::new(self.items),
+            left: self.len,
+        }
+    }
+
+    pub fn to_witness(&self) -> Witness {
+        Witness::new(self.iter().map(<[u8]>::to_vec).collect())
+    }
+}
+
+pub struct WitnessIter<'a> {
+    reader: SliceReader<'a>,
+    left: usize,
+}
+
+impl<'a> Iterator for WitnessIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+        // Already validated by WitnessRef::read_from.
+        let item = ScriptRef::read_from(&mut self.reader).expect("validated witness");
+        self.left -= 1;
+        Some(item.as_bytes())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TransactionInputRef<'a> {
+    pub previous_output: &'a [u8; 36],
+    pub script_sig: ScriptRef<'a>,
+    pub sequence: u32,
+    pub witness: WitnessRef<'a>,
+}
+
+impl<'a> TransactionInputRef<'a> {
+    /// Parses an input in the same layout as `TransactionInput::from_bytes`;
+    /// the witness is left empty.
+    pub fn parse(bytes: &'a [u8]) -> Result<(Self, usize), BitcoinError> {
+        parse_with(bytes, Self::read_from)
+    }
+
+    pub fn read_from(reader: &mut SliceReader<'a>) -> Result<Self, BitcoinError> {
+        Ok(TransactionInputRef {
+            previous_output: reader.read_array()?,
+            script_sig: ScriptRef::read_from(reader)?,
+            sequence: reader.read_u32_le()?,
+            witness: WitnessRef::default(),
+        })
+    }
+
+    pub fn outpoint(&self) -> OutPoint {
+        let (outpoint, _) = OutPoint::from_bytes(self.previous_output).unwrap();
+        outpoint
+    }
+
+    pub fn to_input(&self) -> TransactionInput {
+        TransactionInput::new(self.outpoint(), self.script_sig.to_script(), self.sequence)
+            .with_witness(self.witness.to_witness())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TransactionOutputRef<'a> {
+    pub value: u64,
+    pub script_pubkey: ScriptRef<'a>,
+}
+
+impl<'a> TransactionOutputRef<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<(Self, usize), BitcoinError> {
+        parse_with(bytes, Self::read_from)
+    }
+
+    pub fn read_from(reader: &mut SliceReader<'a>) -> Result<Self, BitcoinError> {
+        Ok(TransactionOutputRef {
+            value: reader.read_u64_le()?,
+            script_pubkey: ScriptRef::read_from(reader)?,
+        })
+    }
+
+    pub fn to_output(&self) -> TransactionOutput {
+        TransactionOutput::new(self.value, self.script_pubkey.to_script())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TransactionRef<'a> {
+    raw: &'a [u8],
+    pub version: u32,
+    pub lock_time: u32,
+    inputs: &'a [u8],
+    input_count: usize,
+    outputs: &'a [u8],
+    output_count: usize,
+    // Empty for the legacy encoding.
+    witnesses: &'a [u8],
+}
+
+impl<'a> TransactionRef<'a> {
+    /// Parses a legacy or segwit transaction, accepting exactly the
+    /// encodings `BitcoinTransaction::from_bytes` does.
+    pub fn parse(bytes: &'a [u8]) -> Result<(Self, usize), BitcoinError> {
+        parse_with(bytes, Self::read_from)
+    }
+
+    pub fn read_from(reader: &mut SliceReader<'a>) -> Result<Self, BitcoinError> {
+        let raw = reader.remaining();
+        let start = reader.position();
+        let version = reader.read_u32_le()?;
+
+        // A zero byte where the input count goes is the segwit marker unless
+        // the next byte is also zero (no inputs, no outputs).
+        let segwit = match reader.remaining() {
+            [0x00, 0x01, ..] => true,
+            [0x00, 0x00, ..] => false,
+            [0x00, _, ..] => return Err(BitcoinError::InvalidFormat),
+            _ => false,
+        };
+        if segwit {
+            reader.read_exact(2)?;
+        }
+
+        let count = reader.read_compact_size()?;
+        let (inputs, input_count) = read_region(reader, count, |reader| {
+            TransactionInputRef::read_from(reader).map(drop)
+        })?;
+
+        let count = reader.read_compact_size()?;
+        let (outputs, output_count) = read_region(reader, count, |reader| {
+            TransactionOutputRef::read_from(reader).map(drop)
+        })?;
+
+        let mut any_witness = false;
+        let count = if segwit { input_count as u64 } else { 0 };
+        let (witnesses, _) = read_region(reader, count, |reader| {
+            any_witness |= !WitnessRef::read_from(reader)?.is_empty();
+            Ok(())
+        })?;
+        if segwit && !any_witness {
+            return Err(BitcoinError::InvalidFormat);
+        }
+
+        let lock_time = reader.read_u32_le()?;
+
+        Ok(TransactionRef {
+            raw: &raw[..reader.position() - start],
+            version,
+            lock_time,
+            inputs,
+            input_count,
+            outputs,
+            output_count,
+            witnesses,
+        })
+    }
+
+    /// The exact bytes this transaction was parsed from.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    pub fn has_witness(&self) -> bool {
+        !self.witnesses.is_empty()
+    }
+
+    pub fn input_count(&self) -> usize {
+        self.input_count
+    }
+
+    pub fn output_count(&self) -> usize {
+        self.output_count
+    }
+
+    pub fn inputs(&self) -> InputIter<'a> {
+        InputIter {
+            inputs: SliceReader::new(self.inputs),
+            witnesses: SliceReader::new(self.witnesses),
+            left: self.input_count,
+        }
+    }
+
+    pub fn outputs(&self) -> OutputIter<'a> {
+        OutputIter {
+            outputs: SliceReader::new(self.outputs),
+            left: self.output_count,
+        }
+    }
+
+    pub fn to_transaction(&self) -> BitcoinTransaction {
+        BitcoinTransaction::new(
+            self.version,
+            self.inputs().map(|input| input.to_input()).collect(),
+            self.outputs().map(|output| output.to_output()).collect(),
+            self.lock_time,
+        )
+    }
+}
+
+pub struct InputIter<'a> {
+    inputs: SliceReader<'a>,
+    witnesses: SliceReader<'a>,
+    left: usize,
+}
+
+impl<'a> Iterator for InputIter<'a> {
+    type Item = TransactionInputRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+        // Already validated by TransactionRef::read_from.
+        let mut input = TransactionInputRef::read_from(&mut self.inputs).expect("validated input");
+        if !self.witnesses.is_empty() {
+            input.witness = WitnessRef::read_from(&mut self.witnesses).expect("validated witness");
+        }
+        self.left -= 1;
+        Some(input)
+    }
+}
+
+pub struct OutputIter<'a> {
+    outputs: SliceReader<'a>,
+    left: usize,
+}
+
+impl<'a> Iterator for OutputIter<'a> {
+    type Item = TransactionOutputRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+        // Already validated by TransactionRef::read_from.
+        let output = TransactionOutputRef::read_from(&mut self.outputs).expect("validated output");
+        self.left -= 1;
+        Some(output)
+    }
+}
@@ -10,6 +10,48 @@ mod tests {
         txid
     }
 
+    // The genesis block's coinbase transaction.
+    #[cfg(feature = "sha2")]
+    fn genesis_coinbase() -> BitcoinTransaction {
+        let input = TransactionInput::new(
+            OutPoint::new([0u8; 32], 0xFFFFFFFF),
+            Script::new(hex::decode("04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73").unwrap()),
+            0xFFFFFFFF,
+        );
+        let output = TransactionOutput::new(
+            5_000_000_000,
+            Script::new(hex::decode("4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac").unwrap()),
+        );
+        BitcoinTransaction::new(1, vec![input], vec![output], 0)
+    }
+
+    // Block 100000, with hashes in internal byte order.
+    #[cfg(feature = "sha2")]
+    const BLOCK_100000_MERKLE_ROOT: [u8; 32] =
+        txid!("6657a9252aacd5c0b2940996ecff952228c3067cc38d4885efb5a4ac4247e9f3").0;
+
+    #[cfg(feature = "sha2")]
+    fn block_100000_txids() -> Vec<Txid> {
+        vec![
+            txid!("876dd0a3ef4a2816ffd1c12ab649825a958b0ff3bb3d6f3e1250f13ddbf0148c"),
+            txid!("c40297f730dd7b5a99567eb8d27b78758f607507c52292d02d4031895b52f2ff"),
+            txid!("c46e239ab7d28e2c019b6d66ad8fae98a56ef1f21aeecb94d1b1718186f05963"),
+            txid!("1d0cb83721529a062d9675b98d6e5c587e4a770fc84ed00abc5a5de04568a6e9"),
+        ]
+    }
+
+    #[cfg(feature = "sha2")]
+    fn genesis_header() -> BlockHeader {
+        BlockHeader::new(
+            1,
+            BlockHash([0u8; 32]),
+            genesis_coinbase().txid().0,
+            1231006505,
+            0x1D00FFFF,
+            2083236893,
+        )
+    }
+
     #[test]
     fn test_compact_size_serialization() {
         let tests = vec![
@@ -66,6 +108,17 @@ mod tests {
         assert_eq!(consumed, bytes.len());
     }
 
+    #[test]
+    fn test_tx_output_roundtrip() {
+        let output =
+            TransactionOutput::new(0x0102030405060708, Script::new(vec![0x00, 0x14, 0xAA]));
+        let bytes = output.to_bytes();
+        assert_eq!(&bytes[..8], &0x0102030405060708u64.to_le_bytes());
+        let (parsed, consumed) = TransactionOutput::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, output);
+        assert_eq!(consumed, bytes.len());
+    }
+
     #[test]
     fn test_bitcoin_tx_roundtrip() {
         let inputs = vec![TransactionInput::new(
@@ -73,7 +126,11 @@ mod tests {
             Script::new(vec![0x01, 0x02]),
             0xFFFFFFFF,
         )];
-        let tx = BitcoinTransaction::new(2, inputs.clone(), 1000);
+        let outputs = vec![TransactionOutput::new(
+            50_000,
+            Script::new(vec![0x76, 0xA9, 0x14, 0x88, 0xAC]),
+        )];
+        let tx = BitcoinTransaction::new(2, inputs.clone(), outputs.clone(), 1000);
         let bytes = tx.to_bytes();
         let (parsed, consumed) = BitcoinTransaction::from_bytes(&bytes).unwrap();
         assert_eq!(parsed, tx);
@@ -87,7 +144,8 @@ mod tests {
             Script::new(vec![0xDE, 0xAD, 0xBE, 0xEF]),
             0xABCDEF01,
         );
-        let tx = BitcoinTransaction::new(1, vec![input], 999);
+        let output = TransactionOutput::new(12_345, Script::new(vec![0x6A]));
+        let tx = BitcoinTransaction::new(1, vec![input], vec![output], 999);
 
         let json = serde_json::to_string_pretty(&tx).unwrap();
         let parsed: BitcoinTransaction = serde_json::from_str(&json).unwrap();
@@ -104,10 +162,1646 @@ mod tests {
             Script::new(vec![0x01, 0x02, 0x03]),
             0xFFFFFFFF,
         );
-        let tx = BitcoinTransaction::new(1, vec![input], 0);
+        let tx_output = TransactionOutput::new(5_000, Script::new(vec![0x51]));
+        let tx = BitcoinTransaction::new(1, vec![input], vec![tx_output], 0);
         let output = format!("{}", tx);
         assert!(output.contains("Version: 1"));
+        assert!(output.contains("Value: 5000"));
         assert!(output.contains("Lock Time: 0"));
         assert!(output.contains("Previous Output Vout: 7"));
     }
+
+    #[test]
+    fn test_serde_utils_field_adapters() {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct ApiOutput {
+            #[serde(with = "serde_utils::hex_u32")]
+            sequence: u32,
+            #[serde(with = "serde_utils::sat_amount")]
+            value: u64,
+            #[serde(with = "serde_utils::script_hex")]
+            script_pubkey: Script,
+        }
+
+        let output = ApiOutput {
+            sequence: 0xFFFFFFFE,
+            value: 150_000_000,
+            script_pubkey: Script::new(vec![0x76, 0xA9]),
+        };
+
+        let json = serde_json::to_string(&output).unwrap();
+        assert_eq!(
+            json,
+            r#"{"sequence":"feffffff","value":1.5,"script_pubkey":"76a9"}"#
+        );
+        let parsed: ApiOutput = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, output);
+
+        let bad = r#"{"sequence":"ffff","value":1.5,"script_pubkey":"76a9"}"#;
+        assert!(serde_json::from_str::<ApiOutput>(bad).is_err());
+        let negative = r#"{"sequence":"feffffff","value":-1.0,"script_pubkey":"76a9"}"#;
+        assert!(serde_json::from_str::<ApiOutput>(negative).is_err());
+    }
+
+    #[test]
+    fn test_tx_annotations() {
+        use annotation::{TxAnnotation, TxAnnotations};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct LockTimeTag {
+            tag: u32,
+        }
+
+        impl TxAnnotation for LockTimeTag {
+            const PROTOCOL: &'static str = "locktime-tag";
+
+            fn detect(tx: &BitcoinTransaction) -> Option<Self> {
+                (tx.lock_time != 0).then_some(LockTimeTag { tag: tx.lock_time })
+            }
+        }
+
+        let tagged = BitcoinTransaction::new(1, vec![], vec![], 42);
+        let untagged = BitcoinTransaction::new(1, vec![], vec![], 0);
+
+        let mut annotations = TxAnnotations::new();
+        assert!(!annotations.detect::<LockTimeTag>(&untagged).unwrap());
+        assert!(annotations.is_empty());
+        assert!(annotations.detect::<LockTimeTag>(&tagged).unwrap());
+        assert_eq!(
+            annotations.protocols().collect::<Vec<_>>(),
+            ["locktime-tag"]
+        );
+
+        let json = serde_json::to_string(&annotations).unwrap();
+        let parsed: TxAnnotations = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            parsed.get::<LockTimeTag>().unwrap(),
+            Some(LockTimeTag { tag: 42 })
+        );
+
+        annotations.remove::<LockTimeTag>();
+        assert_eq!(annotations.get::<LockTimeTag>().unwrap(), None);
+    }
+
+    #[test]
+    fn test_script_length_overflow() {
+        let mut max_len = vec![0xFF];
+        max_len.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            Script::from_bytes(&max_len),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+
+        let mut above_u32 = vec![0xFF];
+        above_u32.extend_from_slice(&(u32::MAX as u64 + 1).to_le_bytes());
+        assert_eq!(
+            Script::from_bytes(&above_u32),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+
+        // Without a script limit the length itself overflows.
+        let unlimited = DecodeLimits {
+            max_script_size: u64::MAX,
+            ..DecodeLimits::default()
+        };
+        assert_eq!(
+            Script::consensus_decode_with_limits(&mut SliceReader::new(&max_len), &unlimited),
+            Err(BitcoinError::NumericOverflow)
+        );
+    }
+
+    #[test]
+    fn test_txid_and_outpoint_sets() {
+        use sets::{OutPointSet, TxidSet};
+
+        let mut txids: TxidSet = [3, 1, 2, 1]
+            .into_iter()
+            .map(|v| Txid(dummy_txid(v)))
+            .collect();
+        assert_eq!(txids.len(), 3);
+        assert!(txids.contains(&Txid(dummy_txid(2))));
+        assert!(!txids.contains(&Txid(dummy_txid(4))));
+        assert!(txids.insert(Txid(dummy_txid(4))));
+        assert!(!txids.insert(Txid(dummy_txid(4))));
+        assert!(txids.remove(&Txid(dummy_txid(1))));
+        assert!(!txids.remove(&Txid(dummy_txid(1))));
+        assert!(txids.as_slice().windows(2).all(|w| w[0] < w[1]));
+
+        let mut outpoints = OutPointSet::new();
+        outpoints.extend([
+            OutPoint::new(dummy_txid(1), 1),
+            OutPoint::new(dummy_txid(1), 0),
+        ]);
+        assert_eq!(
+            outpoints.iter().next(),
+            Some(&OutPoint::new(dummy_txid(1), 0))
+        );
+
+        let json = serde_json::to_string(&outpoints).unwrap();
+        let parsed: OutPointSet = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, outpoints);
+
+        // Unsorted input with duplicates is normalized on the way in.
+        let unsorted = format!(
+            "[\"{}\", \"{}\", \"{}\"]",
+            hex::encode(dummy_txid(9)),
+            hex::encode(dummy_txid(5)),
+            hex::encode(dummy_txid(9))
+        );
+        let parsed: TxidSet = serde_json::from_str(&unsorted).unwrap();
+        assert_eq!(
+            parsed.into_iter().collect::<Vec<_>>(),
+            vec![Txid(dummy_txid(5)), Txid(dummy_txid(9))]
+        );
+    }
+
+    #[test]
+    fn test_hex_literal_macros() {
+        const TXID: Txid =
+            txid!("00000000000000000000000000000000000000000000000000000000000000ab");
+        const PREVOUT: OutPoint = outpoint!(
+            "00000000000000000000000000000000000000000000000000000000000000AB:4294967295"
+        );
+
+        assert_eq!(TXID, Txid(dummy_txid(0xAB)));
+        assert_eq!(PREVOUT, OutPoint::new(dummy_txid(0xAB), u32::MAX));
+        assert_eq!(script_hex!("76a914"), Script::new(vec![0x76, 0xA9, 0x14]));
+        assert_eq!(script_hex!(""), Script::new(vec![]));
+    }
+
+    #[test]
+    fn test_bitcoin_tx_truncated() {
+        let inputs = vec![TransactionInput::new(
+            OutPoint::new(dummy_txid(1), 0),
+            Script::new(vec![0x01, 0x02]),
+            0xFFFFFFFF,
+        )];
+        let outputs = vec![TransactionOutput::new(1, Script::new(vec![0x51]))];
+        let bytes = BitcoinTransaction::new(2, inputs, outputs, 1000).to_bytes();
+
+        for len in 0..bytes.len() {
+            assert_eq!(
+                BitcoinTransaction::from_bytes(&bytes[..len]),
+                Err(BitcoinError::InsufficientBytes),
+                "truncated to {} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_segwit_tx_roundtrip() {
+        let segwit_input = TransactionInput::new(
+            OutPoint::new(dummy_txid(1), 0),
+            Script::new(vec![]),
+            0xFFFFFFFD,
+        )
+        .with_witness(Witness::new(vec![vec![0x30, 0x44], vec![0x02; 33]]));
+        let legacy_input =
+            TransactionInput::new(OutPoint::new(dummy_txid(2), 1), Script::new(vec![0x51]), 0);
+        let outputs = vec![TransactionOutput::new(1_000, Script::new(vec![0x00, 0x14]))];
+        let tx = BitcoinTransaction::new(2, vec![segwit_input, legacy_input], outputs, 0);
+        assert!(tx.has_witness());
+
+        let bytes = tx.to_bytes();
+        assert_eq!(&bytes[4..6], &[0x00, 0x01]);
+        let (parsed, consumed) = BitcoinTransaction::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, tx);
+        assert_eq!(consumed, bytes.len());
+
+        for len in 0..bytes.len() {
+            assert_eq!(
+                BitcoinTransaction::from_bytes(&bytes[..len]),
+                Err(BitcoinError::InsufficientBytes),
+                "truncated to {} bytes",
+                len
+            );
+        }
+
+        let legacy = tx.to_bytes_legacy();
+        assert_eq!(legacy[4], 0x02);
+        let (stripped, _) = BitcoinTransaction::from_bytes(&legacy).unwrap();
+        assert!(!stripped.has_witness());
+        assert_eq!(
+            stripped.inputs[0].previous_output,
+            tx.inputs[0].previous_output
+        );
+        assert_eq!(stripped.to_bytes(), legacy);
+    }
+
+    #[test]
+    fn test_segwit_tx_invalid_marker() {
+        let input = TransactionInput::new(OutPoint::new(dummy_txid(1), 0), Script::new(vec![]), 0)
+            .with_witness(Witness::new(vec![vec![0x01]]));
+        let tx = BitcoinTransaction::new(1, vec![input], vec![], 0);
+        let mut bytes = tx.to_bytes();
+
+        bytes[5] = 0x02;
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&bytes),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        // Marker and flag present but every witness stack empty.
+        let mut no_witness = tx.to_bytes_legacy();
+        no_witness.splice(4..4, [0x00, 0x01]);
+        no_witness.splice(no_witness.len() - 4..no_witness.len() - 4, [0x00]);
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&no_witness),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_witness_roundtrip_and_accessors() {
+        let witness = Witness::new(vec![vec![0xAA; 64], vec![0x51], vec![0xC0; 33]]);
+        let bytes = witness.to_bytes();
+        assert_eq!(bytes[0], 3);
+        let (parsed, consumed) = Witness::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, witness);
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(
+            Witness::from_bytes(&[0x02, 0x01, 0xAA]),
+            Err(BitcoinError::InsufficientBytes)
+        );
+
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness.nth(1), Some(&[0x51][..]));
+        assert_eq!(witness.nth(3), None);
+        assert_eq!(witness.last(), Some(&[0xC0; 33][..]));
+        assert_eq!(witness.tapscript(), Some(&[0x51][..]));
+
+        let mut with_annex = witness.clone();
+        with_annex.push(vec![0x50, 0x01]);
+        assert_eq!(with_annex.tapscript(), Some(&[0x51][..]));
+        assert_eq!(Witness::new(vec![vec![0x01]]).tapscript(), None);
+
+        let small = Witness::new(vec![vec![0xDE, 0xAD], vec![]]);
+        let json = serde_json::to_string(&small).unwrap();
+        assert_eq!(json, r#"["dead",""]"#);
+        let parsed: Witness = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, small);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_txid_and_wtxid() {
+        let coinbase = genesis_coinbase();
+        assert_eq!(
+            hex::encode(coinbase.txid().0),
+            "3ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a"
+        );
+        assert_eq!(coinbase.wtxid(), coinbase.txid());
+
+        let mut segwit = coinbase.clone();
+        segwit.inputs[0].witness = Witness::new(vec![vec![0x00; 32]]);
+        assert_eq!(segwit.txid(), coinbase.txid());
+        assert_ne!(segwit.wtxid(), segwit.txid());
+    }
+
+    #[test]
+    fn test_block_header_roundtrip() {
+        let header = BlockHeader::new(
+            0x20000000,
+            BlockHash(dummy_txid(0x11)),
+            dummy_txid(0x22),
+            1_700_000_000,
+            0x170FFFFF,
+            42,
+        );
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), BlockHeader::SIZE);
+        assert_eq!(&bytes[4..36], &dummy_txid(0x11));
+        let (parsed, consumed) = BlockHeader::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(consumed, 80);
+        assert_eq!(
+            BlockHeader::from_bytes(&bytes[..79]),
+            Err(BitcoinError::InsufficientBytes)
+        );
+
+        let json = serde_json::to_string(&header).unwrap();
+        assert!(json.contains(&hex::encode(dummy_txid(0x22))));
+        let parsed: BlockHeader = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, header);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_genesis_block_hash() {
+        let header = genesis_header();
+        assert_eq!(
+            hex::encode(header.block_hash().0),
+            "6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000"
+        );
+    }
+
+    #[test]
+    fn test_block_roundtrip() {
+        let header = BlockHeader::new(1, BlockHash([0u8; 32]), [0u8; 32], 0, 0x207FFFFF, 0);
+        let coinbase = BitcoinTransaction::new(
+            1,
+            vec![
+                TransactionInput::new(
+                    OutPoint::new([0u8; 32], 0xFFFFFFFF),
+                    Script::new(vec![0x51]),
+                    0xFFFFFFFF,
+                )
+                .with_witness(Witness::new(vec![vec![0u8; 32]])),
+            ],
+            vec![TransactionOutput::new(
+                5_000_000_000,
+                Script::new(vec![0x51]),
+            )],
+            0,
+        );
+        let spend = BitcoinTransaction::new(
+            2,
+            vec![TransactionInput::new(
+                OutPoint::new(dummy_txid(7), 0),
+                Script::new(vec![]),
+                0,
+            )],
+            vec![TransactionOutput::new(1_000, Script::new(vec![0x6A]))],
+            0,
+        );
+        let block = Block::new(header, vec![coinbase, spend]);
+
+        let bytes = block.to_bytes();
+        let (parsed, consumed) = Block::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, block);
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(
+            Block::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(BitcoinError::InsufficientBytes)
+        );
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_genesis_block_parse() {
+        let genesis = Block::new(genesis_header(), vec![genesis_coinbase()]);
+        let raw = genesis.to_bytes();
+        assert_eq!(raw.len(), 285);
+
+        let (parsed, _) = Block::from_bytes(&raw).unwrap();
+        assert_eq!(
+            hex::encode(parsed.header.block_hash().0),
+            "6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000"
+        );
+        assert_eq!(parsed.txdata[0].txid().0, parsed.header.merkle_root);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_merkle_root() {
+        use merkle::merkle_root;
+
+        assert_eq!(merkle_root(&[]), None);
+        let single = Txid(dummy_txid(1));
+        assert_eq!(merkle_root(std::slice::from_ref(&single)), Some(single.0));
+
+        let txids = block_100000_txids();
+        assert_eq!(merkle_root(&txids), Some(BLOCK_100000_MERKLE_ROOT));
+
+        // An odd count duplicates the last node.
+        let mut odd = txids[..3].to_vec();
+        let odd_root = merkle_root(&odd).unwrap();
+        odd.push(txids[2].clone());
+        assert_eq!(merkle_root(&odd), Some(odd_root));
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_block_check_merkle_root() {
+        let mut genesis = Block::new(genesis_header(), vec![genesis_coinbase()]);
+        assert!(genesis.check_merkle_root());
+
+        genesis.txdata[0].lock_time = 1;
+        assert!(!genesis.check_merkle_root());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_merkle_block_roundtrip_and_extract() {
+        use merkle::{MerkleBlock, PartialMerkleTree};
+
+        let txids = block_100000_txids();
+        let tree = PartialMerkleTree::from_txids(&txids, &[false, true, false, true]).unwrap();
+        let header = BlockHeader::new(
+            1,
+            BlockHash([0u8; 32]),
+            BLOCK_100000_MERKLE_ROOT,
+            1293623863,
+            0x1B04864C,
+            274148111,
+        );
+        let merkle_block = MerkleBlock { header, txn: tree };
+
+        let bytes = merkle_block.to_bytes();
+        let (parsed, consumed) = MerkleBlock::from_bytes(&bytes).unwrap();
+        assert_eq!(consumed, bytes.len());
+        let (mut matches, mut indexes) = (vec![], vec![]);
+        parsed.extract_matches(&mut matches, &mut indexes).unwrap();
+        assert_eq!(matches, vec![txids[1].clone(), txids[3].clone()]);
+        assert_eq!(indexes, vec![1, 3]);
+
+        let mut wrong_root = parsed.clone();
+        wrong_root.header.merkle_root = [0u8; 32];
+        assert_eq!(
+            wrong_root.extract_matches(&mut vec![], &mut vec![]),
+            Err(BitcoinError::MerkleRootMismatch)
+        );
+
+        let mut extra_hash = parsed.clone();
+        extra_hash.txn.hashes.push([0u8; 32]);
+        assert_eq!(
+            extra_hash.txn.extract_matches(&mut vec![], &mut vec![]),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        let none_matched = PartialMerkleTree::from_txids(&txids, &[false; 4]).unwrap();
+        assert_eq!(
+            PartialMerkleTree::from_txids(&txids, &[true; 3]),
+            Err(BitcoinError::InvalidFormat)
+        );
+        let mut matches = vec![];
+        let root = none_matched
+            .extract_matches(&mut matches, &mut vec![])
+            .unwrap();
+        assert_eq!(root, BLOCK_100000_MERKLE_ROOT);
+        assert!(matches.is_empty());
+        assert_eq!(none_matched.hashes.len(), 1);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_partial_merkle_tree_rejects_duplicate_branch() {
+        use merkle::PartialMerkleTree;
+
+        // Three txids with the last duplicated has the same root as the
+        // three-txid tree; the duplicated right subtree must be rejected.
+        let txids = block_100000_txids();
+        let mut duplicated = txids[..3].to_vec();
+        duplicated.push(txids[2].clone());
+        let tree = PartialMerkleTree::from_txids(&duplicated, &[false, false, true, true]).unwrap();
+        assert_eq!(
+            tree.extract_matches(&mut vec![], &mut vec![]),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_merkle_proof_inclusion() {
+        use merkle::{MerkleProof, merkle_root, verify_tx_inclusion};
+
+        let header = BlockHeader::new(1, BlockHash([0u8; 32]), BLOCK_100000_MERKLE_ROOT, 0, 0, 0);
+        let txids = block_100000_txids();
+        for txid in &txids {
+            let proof = MerkleProof::from_txids(&txids, txid).unwrap();
+            assert_eq!(proof.branch.len(), 2);
+            assert!(verify_tx_inclusion(txid, &proof, &header));
+        }
+
+        let proof = MerkleProof::from_txids(&txids, &txids[2]).unwrap();
+        assert_eq!(proof.pos, 2);
+        assert!(!verify_tx_inclusion(&txids[1], &proof, &header));
+        assert_eq!(MerkleProof::from_txids(&txids, &Txid(dummy_txid(9))), None);
+
+        // The last transaction of an odd-sized level is paired with itself.
+        let odd = &txids[..3];
+        let proof = MerkleProof::from_txids(odd, &odd[2]).unwrap();
+        assert_eq!(proof.branch[0], odd[2].0);
+        assert_eq!(Some(proof.root(&odd[2])), merkle_root(odd));
+
+        let single = MerkleProof::from_txids(&txids[..1], &txids[0]).unwrap();
+        assert!(single.branch.is_empty());
+        assert_eq!(single.root(&txids[0]), txids[0].0);
+
+        // Position bits above the branch depth would let one proof claim
+        // several positions.
+        let mut proof = MerkleProof::from_txids(&txids, &txids[1]).unwrap();
+        proof.pos |= 1 << 2;
+        assert_eq!(proof.root(&txids[1]), BLOCK_100000_MERKLE_ROOT);
+        assert!(!verify_tx_inclusion(&txids[1], &proof, &header));
+        assert_eq!(
+            MerkleProof::from_electrum(proof.pos, &proof.to_electrum().1),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_consensus_encode_decode_streaming() {
+        use std::io::{self, Read};
+
+        // Hands out one byte per read call, like a slow socket.
+        struct OneByteReader<'a>(&'a [u8]);
+
+        impl Read for OneByteReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0.is_empty() || buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = self.0[0];
+                self.0 = &self.0[1..];
+                Ok(1)
+            }
+        }
+
+        let tx = BitcoinTransaction::new(
+            2,
+            vec![
+                TransactionInput::new(OutPoint::new(dummy_txid(3), 1), Script::new(vec![]), 0)
+                    .with_witness(Witness::new(vec![vec![0x01; 72], vec![0x02; 33]])),
+            ],
+            vec![TransactionOutput::new(9_000, Script::new(vec![0x51; 300]))],
+            0,
+        );
+
+        let mut stream = Vec::new();
+        let first = tx.consensus_encode(&mut stream).unwrap();
+        let second = tx.consensus_encode(&mut stream).unwrap();
+        assert_eq!(first, tx.to_bytes().len());
+        assert_eq!(first + second, stream.len());
+
+        let mut reader = OneByteReader(&stream);
+        let (a, a_len) = BitcoinTransaction::consensus_decode(&mut reader).unwrap();
+        let (b, b_len) = BitcoinTransaction::consensus_decode(&mut reader).unwrap();
+        assert_eq!((a, a_len), (tx.clone(), first));
+        assert_eq!((b, b_len), (tx, second));
+        assert_eq!(
+            BitcoinTransaction::consensus_decode(&mut reader),
+            Err(BitcoinError::InsufficientBytes)
+        );
+    }
+
+    #[test]
+    fn test_consensus_decode_io_error() {
+        use std::io::{self, Read};
+
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(
+                    io::ErrorKind::ConnectionReset,
+                    "peer went away",
+                ))
+            }
+        }
+
+        assert_eq!(
+            OutPoint::consensus_decode(&mut FailingReader),
+            Err(BitcoinError::Io(io::ErrorKind::ConnectionReset))
+        );
+    }
+
+    #[test]
+    fn test_transaction_ref_view() {
+        use rust_week_3_exercises::view::TransactionRef;
+
+        let segwit_input = TransactionInput::new(
+            OutPoint::new(dummy_txid(1), 0),
+            Script::new(vec![]),
+            0xFFFFFFFD,
+        )
+        .with_witness(Witness::new(vec![vec![0x30, 0x44], vec![0x02; 33]]));
+        let legacy_input =
+            TransactionInput::new(OutPoint::new(dummy_txid(2), 1), Script::new(vec![0x51]), 7);
+        let outputs = vec![TransactionOutput::new(1_000, Script::new(vec![0x00, 0x14]))];
+        let tx = BitcoinTransaction::new(2, vec![segwit_input, legacy_input], outputs, 9);
+
+        for bytes in [tx.to_bytes(), tx.to_bytes_legacy()] {
+            let (view, consumed) = TransactionRef::parse(&bytes).unwrap();
+            assert_eq!(consumed, bytes.len());
+            assert_eq!(view.as_bytes(), &bytes[..]);
+            assert_eq!(view.input_count(), 2);
+            assert_eq!(view.output_count(), 1);
+
+            let (owned, _) = BitcoinTransaction::from_bytes(&bytes).unwrap();
+            assert_eq!(view.to_transaction(), owned);
+
+            // Scripts borrow from the source buffer rather than copying.
+            let input = view.inputs().nth(1).unwrap();
+            assert_eq!(input.outpoint(), OutPoint::new(dummy_txid(2), 1));
+            assert_eq!(input.sequence, 7);
+            assert!(
+                bytes
+                    .as_ptr_range()
+                    .contains(&input.script_sig.as_bytes().as_ptr())
+            );
+            let output = view.outputs().next().unwrap();
+            assert_eq!(output.script_pubkey.as_bytes(), &[0x00, 0x14]);
+            assert!(
+                bytes
+                    .as_ptr_range()
+                    .contains(&output.script_pubkey.as_bytes().as_ptr())
+            );
+
+            for len in 0..bytes.len() {
+                assert_eq!(
+                    TransactionRef::parse(&bytes[..len]),
+                    Err(BitcoinError::InsufficientBytes),
+                    "truncated to {} bytes",
+                    len
+                );
+            }
+        }
+
+        let bytes = tx.to_bytes();
+        let (view, _) = TransactionRef::parse(&bytes).unwrap();
+        assert!(view.has_witness());
+        let witness = view.inputs().next().unwrap().witness;
+        assert_eq!(witness.len(), 2);
+        assert_eq!(witness.iter().next(), Some(&[0x30, 0x44][..]));
+        assert_eq!(witness.to_witness(), tx.inputs[0].witness);
+    }
+
+    #[test]
+    fn test_slice_reader() {
+        let bytes = [0x01, 0x00, 0x00, 0x00, 0xFD, 0x00, 0x01, 0xAA, 0xBB, 0xCC];
+        let mut reader = SliceReader::new(&bytes);
+        assert_eq!(reader.read_u32_le(), Ok(1));
+        assert_eq!(reader.read_compact_size(), Ok(0x100));
+        assert_eq!(reader.position(), 7);
+
+        // A failed read doesn't move the cursor.
+        assert_eq!(reader.read_exact(4), Err(BitcoinError::InsufficientBytes));
+        assert_eq!(reader.read_u64_le(), Err(BitcoinError::InsufficientBytes));
+        assert_eq!(reader.position(), 7);
+
+        let rest = reader.read_exact(3).unwrap();
+        assert_eq!(rest, &[0xAA, 0xBB, 0xCC]);
+        assert_eq!(rest.as_ptr(), bytes[7..].as_ptr());
+        assert!(reader.is_empty());
+
+        let mut reader = SliceReader::new(&[0xFD, 0x10, 0x00]);
+        assert_eq!(reader.read_compact_size(), Err(BitcoinError::InvalidFormat));
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_merkle_proof_electrum() {
+        use merkle::MerkleProof;
+
+        let txids = block_100000_txids();
+        let proof = MerkleProof::from_txids(&txids, &txids[0]).unwrap();
+        let (pos, branch) = proof.to_electrum();
+        assert_eq!(pos, 0);
+        assert_eq!(branch.len(), 2);
+        // Sibling txid as Electrum and block explorers display it.
+        assert_eq!(
+            branch[0],
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4"
+        );
+        assert_eq!(MerkleProof::from_electrum(pos, &branch), Ok(proof));
+
+        assert_eq!(
+            MerkleProof::from_electrum(0, &["zz"]),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            MerkleProof::from_electrum(0, &["00ff"]),
+            Err(BitcoinError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_error_display_and_context() {
+        use rust_week_3_exercises::encode::deserialize_detailed;
+        use std::error::Error;
+
+        let input = TransactionInput::new(OutPoint::new(dummy_txid(1), 0), Script::new(vec![]), 0);
+        let tx = BitcoinTransaction::new(1, vec![input], vec![], 0);
+        let bytes = tx.to_bytes();
+
+        // The txid of the first input starts at byte 5 and needs 32 bytes.
+        let err = deserialize_detailed::<BitcoinTransaction>(&bytes[..20]).unwrap_err();
+        assert_eq!(err.error, BitcoinError::InsufficientBytes);
+        assert_eq!(err.context, "BitcoinTransaction");
+        assert_eq!(err.path, "inputs[0].previous_output.txid");
+        assert_eq!((err.offset, err.needed, err.available), (5, Some(32), 15));
+        assert_eq!(
+            err.to_string(),
+            "failed to decode BitcoinTransaction.inputs[0].previous_output.txid at byte 5: \
+             input ended before the value did (needed 32 bytes, 15 available)"
+        );
+        assert_eq!(
+            err.source().unwrap().to_string(),
+            BitcoinError::InsufficientBytes.to_string()
+        );
+
+        // A bad length is reported at the script it belongs to.
+        let mut nested = bytes[..41].to_vec();
+        nested.extend_from_slice(&[0xfd, 0x00, 0x00]);
+        let err = deserialize_detailed::<BitcoinTransaction>(&nested).unwrap_err();
+        assert_eq!(err.error, BitcoinError::InvalidFormat);
+        assert_eq!(err.path, "inputs[0].script_sig");
+        assert_eq!((err.offset, err.needed, err.available), (41, None, 3));
+        assert_eq!(
+            err.to_string(),
+            "failed to decode BitcoinTransaction.inputs[0].script_sig at byte 41: malformed encoding"
+        );
+
+        // A script that runs out wants its whole length from its prefix on.
+        let mut short_script = bytes[..41].to_vec();
+        short_script.extend_from_slice(&[0x05, 0x51, 0x51]);
+        let err = deserialize_detailed::<BitcoinTransaction>(&short_script).unwrap_err();
+        assert_eq!(err.path, "inputs[0].script_sig");
+        assert_eq!((err.offset, err.needed, err.available), (41, Some(6), 3));
+
+        // Witness items are indexed under the input they belong to.
+        let input = TransactionInput::new(OutPoint::new(dummy_txid(1), 0), Script::new(vec![]), 0)
+            .with_witness(Witness::new(vec![vec![0x01], vec![0x02; 4]]));
+        let segwit = BitcoinTransaction::new(2, vec![input], vec![], 0).to_bytes();
+        let err =
+            deserialize_detailed::<BitcoinTransaction>(&segwit[..segwit.len() - 6]).unwrap_err();
+        assert_eq!(err.path, "witnesses[0].items[1]");
+        assert_eq!((err.needed, err.available), (Some(5), 3));
+
+        // Leaf types have no path.
+        let err = deserialize_detailed::<CompactSize>(&[0xFF, 0x00]).unwrap_err();
+        assert_eq!((err.path.as_str(), err.offset), ("", 0));
+        assert_eq!((err.needed, err.available), (Some(9), 2));
+
+        let boxed: Box<dyn Error> = Box::new(BitcoinError::InvalidFormat);
+        assert_eq!(boxed.to_string(), "malformed encoding");
+
+        let (parsed, consumed) = deserialize_detailed::<BitcoinTransaction>(&bytes).unwrap();
+        assert_eq!(parsed, tx);
+        assert_eq!(consumed, bytes.len());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_header_chain_compact_storage() {
+        use headers::HeaderChain;
+
+        let mut headers = vec![genesis_header()];
+        for height in 1..2500u32 {
+            let prev = headers.last().unwrap();
+            // Occasionally step back in time, as the median-time rule allows.
+            let time = if height % 7 == 0 {
+                prev.time - 30
+            } else {
+                prev.time + 600
+            };
+            let bits = if height < 2016 {
+                prev.bits
+            } else {
+                0x1d00_d86a
+            };
+            let version = if height % 1000 == 0 { 2 } else { prev.version };
+            let mut merkle_root = [0u8; 32];
+            merkle_root[..4].copy_from_slice(&height.to_le_bytes());
+            headers.push(BlockHeader::new(
+                version,
+                prev.block_hash(),
+                merkle_root,
+                time,
+                bits,
+                height.wrapping_mul(2_654_435_761),
+            ));
+        }
+
+        let mut chain = HeaderChain::new();
+        for header in &headers {
+            chain.push(header.clone()).unwrap();
+        }
+        assert_eq!(chain.len(), headers.len());
+        assert_eq!(chain.tip(), headers.last());
+        for height in [0, 1, 7, 31, 32, 33, 1000, 2015, 2016, 2017, 2499] {
+            assert_eq!(
+                chain.get(height).as_ref(),
+                Some(&headers[height]),
+                "height {}",
+                height
+            );
+        }
+        assert_eq!(chain.get(2500), None);
+        assert!(chain.encoded_len() < headers.len() * 45);
+
+        let bytes = chain.to_bytes();
+        let (parsed, consumed) = HeaderChain::from_bytes(&bytes).unwrap();
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(parsed, chain);
+        assert_eq!(
+            HeaderChain::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(BitcoinError::InsufficientBytes)
+        );
+
+        let orphan = BlockHeader::new(1, BlockHash([7u8; 32]), [0u8; 32], 0, 0, 0);
+        assert_eq!(chain.push(orphan), Err(BitcoinError::InvalidFormat));
+    }
+
+    #[test]
+    fn test_decode_errors_not_collapsed() {
+        // A one-byte scriptSig with its length in a non-canonical 0xFD prefix.
+        let mut input = OutPoint::new(dummy_txid(1), 0).to_bytes();
+        input.extend_from_slice(&[0xFD, 0x01, 0x00, 0x51]);
+        input.extend_from_slice(&0u32.to_le_bytes());
+
+        assert_eq!(
+            Script::from_bytes(&input[36..]),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            TransactionInput::from_bytes(&input),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        let mut tx = vec![0x01, 0x00, 0x00, 0x00, 0x01];
+        tx.extend_from_slice(&input);
+        tx.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&tx),
+            Err(BitcoinError::InvalidFormat)
+        );
+        let err = encode::deserialize_detailed::<BitcoinTransaction>(&tx).unwrap_err();
+        assert_eq!(err.error, BitcoinError::InvalidFormat);
+        assert_eq!(err.path, "inputs[0].script_sig");
+        assert_eq!(err.offset, 5 + 36);
+
+        // Running out of input is still reported as truncation.
+        assert_eq!(
+            TransactionInput::from_bytes(&input[..38]),
+            Err(BitcoinError::InsufficientBytes)
+        );
+    }
+
+    #[test]
+    fn test_compact_size_lenient() {
+        let non_minimal = [0xFD, 0x01, 0x00];
+        assert_eq!(
+            CompactSize::from_bytes(&non_minimal),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            CompactSize::from_bytes_lenient(&non_minimal),
+            Ok((CompactSize::new(1), 3, false))
+        );
+        assert_eq!(
+            CompactSize::from_bytes_lenient(&[0xFE, 0x00, 0x00, 0x01, 0x00]),
+            Ok((CompactSize::new(0x10000), 5, true))
+        );
+        assert_eq!(
+            CompactSize::from_bytes_lenient(&[0xFF, 0x00]),
+            Err(BitcoinError::InsufficientBytes)
+        );
+
+        for value in [0, 0xFC, 0xFD, 0xFFFF, 0x10000, 0xFFFFFFFF, 0x100000000] {
+            let size = CompactSize::new(value);
+            assert_eq!(size.encoded_len(), size.to_bytes().len());
+        }
+
+        // Lenient decoding reaches the counts and lengths inside a
+        // transaction and a block.
+        let input =
+            TransactionInput::new(OutPoint::new(dummy_txid(1), 0), Script::new(vec![0x51]), 0);
+        let tx = BitcoinTransaction::new(
+            1,
+            vec![input],
+            vec![TransactionOutput::new(5, Script::new(vec![]))],
+            0,
+        );
+        let canonical = tx.to_bytes();
+        assert_eq!(
+            BitcoinTransaction::from_bytes_lenient(&canonical),
+            Ok((tx.clone(), canonical.len(), true))
+        );
+        // Input count at 4, then the script_sig length 36 bytes further on.
+        let mut padded = canonical.clone();
+        padded.splice(41..42, [0xFD, 0x01, 0x00]);
+        padded.splice(4..5, [0xFE, 0x01, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            BitcoinTransaction::from_bytes(&padded),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            BitcoinTransaction::from_bytes_lenient(&padded),
+            Ok((tx.clone(), canonical.len() + 6, false))
+        );
+
+        let block = Block::new(
+            BlockHeader::new(1, BlockHash([0; 32]), [0; 32], 0, 0x207FFFFF, 0),
+            vec![tx],
+        );
+        let mut padded = block.to_bytes();
+        padded.splice(80..81, [0xFD, 0x01, 0x00]);
+        assert_eq!(Block::from_bytes(&padded), Err(BitcoinError::InvalidFormat));
+        assert_eq!(
+            Block::from_bytes_lenient(&padded),
+            Ok((block.clone(), padded.len(), false))
+        );
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let inputs = (0..3)
+            .map(|i| {
+                TransactionInput::new(
+                    OutPoint::new(dummy_txid(i), 0),
+                    Script::new(vec![0x51; 20]),
+                    0,
+                )
+            })
+            .collect();
+        let tx = BitcoinTransaction::new(1, inputs, vec![], 0);
+        let bytes = tx.to_bytes();
+
+        let limit = |limits: DecodeLimits| {
+            BitcoinTransaction::from_bytes_with_limits(&bytes, &limits).map(|(tx, _)| tx)
+        };
+        let defaults = DecodeLimits::default();
+        assert_eq!(limit(defaults), Ok(tx.clone()));
+        assert_eq!(
+            limit(DecodeLimits {
+                max_items: 2,
+                ..defaults
+            }),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+        assert_eq!(
+            limit(DecodeLimits {
+                max_script_size: 19,
+                ..defaults
+            }),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+        assert_eq!(
+            limit(DecodeLimits {
+                max_total_size: bytes.len() - 1,
+                ..defaults
+            }),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+        assert_eq!(
+            limit(DecodeLimits {
+                max_total_size: bytes.len(),
+                ..defaults
+            }),
+            Ok(tx.clone())
+        );
+
+        // Limits travel with the call, so a decode on another thread is
+        // held to them too.
+        let tight = DecodeLimits {
+            max_items: 1,
+            ..defaults
+        };
+        let on_thread = std::thread::scope(|scope| {
+            scope
+                .spawn(|| BitcoinTransaction::from_bytes_with_limits(&bytes, &tight))
+                .join()
+                .unwrap()
+        });
+        assert_eq!(on_thread, Err(BitcoinError::SizeLimitExceeded));
+
+        // Nested values see the limits as well.
+        let header = BlockHeader::new(1, BlockHash([0; 32]), [0; 32], 0, 0, 0);
+        let block_bytes = Block::new(header, vec![tx]).to_bytes();
+        assert_eq!(
+            Block::from_bytes_with_limits(&block_bytes, &tight),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+
+        // A claimed script length past the default cap fails before reading.
+        let huge = [0xFE, 0x01, 0x00, 0x00, 0x01];
+        assert_eq!(
+            Script::from_bytes(&huge),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn test_script_instructions() {
+        use opcodes::{Instruction, Opcode};
+
+        for byte in 0..=255u8 {
+            let op = Opcode::from_u8(byte);
+            assert_eq!(op.to_u8(), byte);
+            if !matches!(op, Opcode::Unknown(_)) {
+                assert_eq!(Opcode::from_name(&op.to_string()), Some(op), "{}", op);
+            }
+        }
+        assert_eq!(Opcode::from_u8(0x76).to_string(), "OP_DUP");
+        assert_eq!(Opcode::from_u8(0x60), Opcode::push_num(16).unwrap());
+        assert_eq!(Opcode::push_num(0), None);
+        assert_eq!(Opcode::push_num(17), None);
+        assert_eq!(Opcode::push_bytes(75).map(Opcode::to_u8), Some(0x4b));
+        assert_eq!(Opcode::push_bytes(76), None);
+        assert_eq!(
+            Opcode::from_name("OP_NOP2"),
+            Some(Opcode::CheckLockTimeVerify)
+        );
+        assert_eq!(Opcode::from_name("OP_17"), None);
+
+        // P2PKH: OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG
+        let p2pkh = script_hex!("76a914000102030405060708090a0b0c0d0e0f1011121388ac");
+        let instructions: Result<Vec<_>, _> = p2pkh.instructions().collect();
+        assert_eq!(
+            instructions.unwrap(),
+            vec![
+                Instruction::Op(Opcode::Dup),
+                Instruction::Op(Opcode::Hash160),
+                Instruction::PushBytes(&p2pkh[3..23]),
+                Instruction::Op(Opcode::EqualVerify),
+                Instruction::Op(Opcode::CheckSig),
+            ]
+        );
+
+        let pushdata = Script::new(vec![
+            0x00, 0x4c, 0x02, 0xAA, 0xBB, 0x4d, 0x01, 0x00, 0xCC, 0x51,
+        ]);
+        let instructions: Result<Vec<_>, _> = pushdata.instructions().collect();
+        assert_eq!(
+            instructions.unwrap(),
+            vec![
+                Instruction::PushBytes(&[]),
+                Instruction::PushBytes(&[0xAA, 0xBB]),
+                Instruction::PushBytes(&[0xCC]),
+                Instruction::Op(Opcode::push_num(1).unwrap()),
+            ]
+        );
+
+        // A push longer than the rest of the script ends the iteration.
+        let truncated = Script::new(vec![0x76, 0x4e, 0x10, 0x00, 0x00, 0x00, 0x01, 0xac]);
+        let mut instructions = truncated.instructions();
+        assert_eq!(instructions.next(), Some(Ok(Instruction::Op(Opcode::Dup))));
+        assert_eq!(
+            instructions.next(),
+            Some(Err(BitcoinError::InsufficientBytes))
+        );
+        assert_eq!(instructions.next(), None);
+    }
+
+    #[test]
+    fn test_script_asm() {
+        let p2pkh = script_hex!("76a91489abcdefabbaabbaabbaabbaabbaabbaabbaabba88ac");
+        let asm =
+            "OP_DUP OP_HASH160 89abcdefabbaabbaabbaabbaabbaabbaabbaabba OP_EQUALVERIFY OP_CHECKSIG";
+        assert_eq!(p2pkh.to_asm(), asm);
+        assert_eq!(Script::from_asm(asm), Ok(p2pkh));
+
+        // Small pushes show as numbers, like bitcoin-cli.
+        let numbers = Script::new(vec![
+            0x00, 0x4f, 0x51, 0x60, 0x02, 0xe8, 0x03, 0x01, 0x81, 0x02, 0xff, 0x00,
+        ]);
+        assert_eq!(numbers.to_asm(), "0 -1 1 16 1000 -1 255");
+        assert_eq!(
+            Script::from_asm("0 -1 1 16 1000 255").unwrap().bytes,
+            vec![0x00, 0x4f, 0x51, 0x60, 0x02, 0xe8, 0x03, 0x02, 0xff, 0x00]
+        );
+
+        // An all-digit hex push with a leading zero isn't mistaken for a
+        // number.
+        let digits = Script::new(vec![0x05, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(digits.to_asm(), "0000000001");
+        assert_eq!(Script::from_asm("0000000001"), Ok(digits));
+
+        // But one that reads as a number within i32 range is ambiguous, and
+        // comes back as that number.
+        let ambiguous = Script::new(vec![0x05, 0x10, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(ambiguous.to_asm(), "1000000000");
+        assert_eq!(
+            Script::from_asm("1000000000").unwrap().bytes,
+            vec![0x04, 0x00, 0xca, 0x9a, 0x3b]
+        );
+
+        assert_eq!(
+            Script::new(vec![0x76, 0x4c, 0x05, 0x01]).to_asm(),
+            "OP_DUP [error]"
+        );
+        assert_eq!(
+            Script::from_asm("OP_BOGUS"),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(Script::from_asm(""), Ok(Script::new(vec![])));
+    }
+
+    #[test]
+    fn test_script_classify() {
+        let hash20 = [0xAB; 20];
+        let hash32 = [0xCD; 32];
+        let with = |prefix: &[u8], body: &[u8], suffix: &[u8]| {
+            Script::new([prefix, body, suffix].concat())
+        };
+
+        let cases = [
+            (with(&[0x21, 0x02], &[0x11; 32], &[0xac]), ScriptType::P2pk),
+            (
+                with(&[0x76, 0xa9, 0x14], &hash20, &[0x88, 0xac]),
+                ScriptType::P2pkh,
+            ),
+            (with(&[0xa9, 0x14], &hash20, &[0x87]), ScriptType::P2sh),
+            (with(&[0x00, 0x14], &hash20, &[]), ScriptType::P2wpkh),
+            (with(&[0x00, 0x20], &hash32, &[]), ScriptType::P2wsh),
+            (with(&[0x51, 0x20], &hash32, &[]), ScriptType::P2tr),
+            (
+                with(&[0x52, 0x02], &[0x01, 0x02], &[]),
+                ScriptType::WitnessUnknown,
+            ),
+            (with(&[0x6a, 0x04], b"test", &[]), ScriptType::OpReturn),
+            (Script::new(vec![0x6a]), ScriptType::OpReturn),
+            // v0 programs must be 20 or 32 bytes.
+            (
+                with(&[0x00, 0x15], &[0u8; 21], &[]),
+                ScriptType::NonStandard,
+            ),
+            (Script::new(vec![0x6a, 0x76]), ScriptType::NonStandard),
+            (
+                with(&[0x21, 0x05], &[0x11; 32], &[0xac]),
+                ScriptType::NonStandard,
+            ),
+        ];
+        for (script, expected) in &cases {
+            assert_eq!(script.classify(), *expected, "{}", script.to_asm());
+        }
+
+        assert!(cases[1].0.is_p2pkh() && !cases[1].0.is_p2sh());
+        assert_eq!(cases[0].0.payload().as_deref(), Some(&cases[0].0[1..34]));
+        assert_eq!(cases[1].0.payload().as_deref(), Some(&hash20[..]));
+        assert_eq!(cases[2].0.payload().as_deref(), Some(&hash20[..]));
+        assert_eq!(cases[5].0.payload().as_deref(), Some(&hash32[..]));
+        assert_eq!(cases[5].0.witness_program(), Some((1, &hash32[..])));
+        assert_eq!(cases[7].0.payload().as_deref(), Some(&b"test"[..]));
+        assert_eq!(cases[8].0.payload(), None);
+        assert_eq!(cases[9].0.payload(), None);
+    }
+
+    #[test]
+    fn test_script_template_constructors() {
+        let hash20 = [0xAB; 20];
+        let hash32 = [0xCD; 32];
+
+        let p2pkh = Script::new_p2pkh(&hash20);
+        assert_eq!(
+            p2pkh,
+            script_hex!("76a914abababababababababababababababababababab88ac")
+        );
+        assert_eq!(p2pkh.classify(), ScriptType::P2pkh);
+        assert_eq!(Script::new_p2sh(&hash20).classify(), ScriptType::P2sh);
+        assert_eq!(Script::new_p2wpkh(&hash20).classify(), ScriptType::P2wpkh);
+        assert_eq!(Script::new_p2wsh(&hash32).classify(), ScriptType::P2wsh);
+        assert_eq!(
+            Script::new_p2tr(&hash32).payload().as_deref(),
+            Some(&hash32[..])
+        );
+
+        let op_return = Script::new_op_return(b"hello").unwrap();
+        assert_eq!(op_return.bytes, b"\x6a\x05hello");
+        assert_eq!(op_return.payload().as_deref(), Some(&b"hello"[..]));
+        // A one-byte push shortened to OP_7 carries the same payload.
+        let small = Script::new_op_return(&[7]).unwrap();
+        assert_eq!(small.bytes, [0x6a, 0x57]);
+        assert_eq!(small.payload().as_deref(), Some(&[7][..]));
+        assert_eq!(
+            small.payload().map(std::borrow::Cow::into_owned),
+            small.op_return_data()
+        );
+        // Long data needs OP_PUSHDATA1.
+        let long = Script::new_op_return(&[0x42; 80]).unwrap();
+        assert_eq!(&long[..3], &[0x6a, 0x4c, 80]);
+        assert_eq!(long.classify(), ScriptType::OpReturn);
+    }
+
+    #[test]
+    fn test_script_builder_minimal_pushes() {
+        use opcodes::Opcode;
+
+        let pushes = |data: &[u8]| ScriptBuilder::new().push_slice(data).into_script().bytes;
+        assert_eq!(pushes(&[]), vec![0x00]);
+        assert_eq!(pushes(&[0x05]), vec![0x55]);
+        assert_eq!(pushes(&[0x81]), vec![0x4f]);
+        assert_eq!(pushes(&[0x11]), vec![0x01, 0x11]);
+        assert_eq!(&pushes(&[0u8; 75])[..1], &[75]);
+        assert_eq!(&pushes(&[0u8; 76])[..2], &[0x4c, 76]);
+        assert_eq!(&pushes(&[0u8; 256])[..3], &[0x4d, 0x00, 0x01]);
+        assert_eq!(&pushes(&[0u8; 65536])[..5], &[0x4e, 0x00, 0x00, 0x01, 0x00]);
+
+        let ints = |n: i64| ScriptBuilder::new().push_int(n).into_script().bytes;
+        assert_eq!(ints(0), vec![0x00]);
+        assert_eq!(ints(-1), vec![0x4f]);
+        assert_eq!(ints(16), vec![0x60]);
+        assert_eq!(ints(17), vec![0x01, 0x11]);
+        assert_eq!(ints(-2), vec![0x01, 0x82]);
+        assert_eq!(ints(128), vec![0x02, 0x80, 0x00]);
+        assert_eq!(ints(-128), vec![0x02, 0x80, 0x80]);
+        assert_eq!(ints(500_000), vec![0x03, 0x20, 0xa1, 0x07]);
+
+        let hash = [0xAB; 20];
+        let p2pkh = ScriptBuilder::new()
+            .push_opcode(Opcode::Dup)
+            .push_opcode(Opcode::Hash160)
+            .push_slice(&hash)
+            .push_opcode(Opcode::EqualVerify)
+            .push_opcode(Opcode::CheckSig)
+            .into_script();
+        assert_eq!(p2pkh, Script::new_p2pkh(&hash));
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        use canonical::CanonicalForm;
+
+        // scriptSig pushes 3 bytes with a needless OP_PUSHDATA1.
+        let input = TransactionInput::new(
+            OutPoint::new(dummy_txid(1), 0),
+            Script::new(vec![0x4c, 0x03, 0xAA, 0xBB, 0xCC]),
+            0,
+        )
+        .with_witness(Witness::new(vec![vec![0x01]]));
+        let broken =
+            TransactionInput::new(OutPoint::new(dummy_txid(2), 0), Script::new(vec![0x4c]), 0);
+        let outputs = vec![TransactionOutput::new(
+            1_000,
+            Script::new(vec![0x4c, 0x01, 0x51]),
+        )];
+        let tx = BitcoinTransaction::new(2, vec![input, broken], outputs, 0);
+
+        let stripped = tx.canonicalize(&CanonicalForm::default());
+        assert!(!stripped.has_witness());
+        assert_eq!(stripped.inputs[0].script_sig, tx.inputs[0].script_sig);
+        assert_eq!(stripped.to_bytes(), tx.to_bytes_legacy());
+
+        let minimal = tx.canonicalize(&CanonicalForm {
+            strip_witness: false,
+            minimal_pushes: true,
+        });
+        assert!(minimal.has_witness());
+        assert_eq!(
+            minimal.inputs[0].script_sig.bytes,
+            vec![0x03, 0xAA, 0xBB, 0xCC]
+        );
+        assert_eq!(minimal.inputs[1].script_sig, tx.inputs[1].script_sig);
+        assert_eq!(minimal.outputs, tx.outputs);
+
+        // Transactions differing only in witness give the same JSON.
+        let (legacy, _) = BitcoinTransaction::from_bytes(&tx.to_bytes_legacy()).unwrap();
+        let form = CanonicalForm::default();
+        assert_eq!(tx.to_canonical_json(&form), legacy.to_canonical_json(&form));
+        assert!(
+            tx.to_canonical_json(&form)
+                .starts_with("{\"version\":2,\"inputs\":[")
+        );
+    }
+
+    #[test]
+    fn test_multisig_scripts() {
+        let keys = [
+            [0x02; 33].to_vec(),
+            [0x03; 33].to_vec(),
+            [0x04; 65].to_vec(),
+        ];
+        let script = Script::new_multisig(2, &keys).unwrap();
+        assert_eq!(script[0], 0x52);
+        assert_eq!(&script[script.len() - 2..], &[0x53, 0xae]);
+        assert_eq!(script.classify(), ScriptType::Multisig);
+
+        let (m, parsed) = script.parse_multisig().unwrap();
+        assert_eq!(m, 2);
+        assert_eq!(parsed, keys.iter().map(Vec::as_slice).collect::<Vec<_>>());
+        assert_eq!(script.payload(), None);
+
+        assert_eq!(
+            Script::new_multisig(0, &keys),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            Script::new_multisig(4, &keys),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            Script::new_multisig(1, &[vec![0x05; 33]]),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        // The key count must match OP_n, and nothing may follow CHECKMULTISIG.
+        let mut wrong_n = script.bytes.clone();
+        let n_pos = wrong_n.len() - 2;
+        wrong_n[n_pos] = 0x52;
+        assert_eq!(Script::new(wrong_n).parse_multisig(), None);
+        let mut trailing = script.bytes.clone();
+        trailing.push(0x75);
+        assert_eq!(Script::new(trailing).classify(), ScriptType::NonStandard);
+    }
+
+    #[test]
+    fn test_format_fixtures() {
+        use rust_week_3_exercises::format;
+
+        assert_eq!(format::format_version(), format::FORMAT_VERSION);
+        assert_eq!(format::check_fixtures(), Ok(()));
+    }
+
+    #[test]
+    fn test_timelock_scripts() {
+        let key = [0x02; 33];
+        let cltv = Script::new_cltv(800_000, &key).unwrap();
+        assert_eq!(&cltv[..5], &[0x03, 0x00, 0x35, 0x0c, 0xb1]);
+        assert_eq!(cltv.parse_cltv(), Some((800_000, &key[..])));
+        assert_eq!(cltv.parse_csv(), None);
+        assert_eq!(cltv.classify(), ScriptType::NonStandard);
+
+        // Small values use OP_n; values with the top bit set need 5 bytes.
+        let csv = Script::new_csv(10, &key).unwrap();
+        assert_eq!(&csv[..2], &[0x5a, 0xb2]);
+        assert_eq!(csv.parse_csv(), Some((10, &key[..])));
+        let far = Script::new_cltv(u32::MAX, &key).unwrap();
+        assert_eq!(far[0], 0x05);
+        assert_eq!(far.parse_cltv(), Some((u32::MAX, &key[..])));
+
+        assert_eq!(
+            Script::new_csv(1 << 31, &key),
+            Err(BitcoinError::InvalidFormat)
+        );
+        assert_eq!(
+            Script::new_cltv(1, &[0x02; 32]),
+            Err(BitcoinError::InvalidFormat)
+        );
+
+        // A non-minimal push of the lock time isn't recognized.
+        let mut padded = vec![0x02, 0x0a, 0x00];
+        padded.extend_from_slice(&csv[1..]);
+        assert_eq!(Script::new(padded).parse_csv(), None);
+    }
+
+    #[test]
+    fn test_tx_filter() {
+        let input = TransactionInput::new(OutPoint::new([0x11; 32], 0), Script::new(vec![]), 5);
+        let tx = BitcoinTransaction::new(
+            2,
+            vec![input],
+            vec![
+                TransactionOutput::new(500_000, Script::new_p2pkh(&[0x22; 20])),
+                TransactionOutput::new(1_000, Script::new_p2tr(&[0x33; 32])),
+            ],
+            0,
+        );
+        let matches = |expr: &str| TxFilter::parse(expr).unwrap().matches(&tx);
+
+        assert!(matches("output.value > 100000"));
+        assert!(matches("output.type==p2tr && outputs >= 2"));
+        // Output comparisons joined by && must hold for the same output.
+        assert!(!matches("output.value > 100000 && output.type == p2tr"));
+        assert!(matches(
+            "output.value > 100000 && output.type == p2tr || version == 2"
+        ));
+        assert!(matches("input.sequence < 10 && lock_time == 0"));
+        assert!(!matches("inputs != 1"));
+
+        for bad in [
+            "",
+            "output.value >",
+            "output.size > 1",
+            "output.type > p2tr",
+            "output.type == p2xx",
+            "version => 2",
+            "version == -1",
+            "version == 2 &&",
+            "(version == 2)",
+        ] {
+            assert_eq!(
+                TxFilter::parse(bad),
+                Err(BitcoinError::InvalidFormat),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_op_return_data() {
+        let anchor = [0xab; 32];
+        let script = Script::new_op_return(&anchor).unwrap();
+        assert_eq!(script.op_return_data(), Some(anchor.to_vec()));
+
+        // Data the builder shortened to a small-number opcode round-trips.
+        for data in [&[][..], &[7], &[0x81]] {
+            let script = Script::new_op_return(data).unwrap();
+            assert_eq!(script.op_return_data(), Some(data.to_vec()));
+        }
+
+        assert!(Script::new_op_return(&[0; MAX_OP_RETURN_DATA]).is_ok());
+        assert_eq!(
+            Script::new_op_return(&[0; MAX_OP_RETURN_DATA + 1]),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+        assert!(Script::new_op_return_with_limit(&[0; 200], 200).is_ok());
+        assert_eq!(
+            Script::new_op_return_with_limit(b"hello", 4),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+        assert_eq!(
+            BitcoinError::SizeLimitExceeded.to_string(),
+            "size limit exceeded"
+        );
+
+        assert_eq!(Script::new(vec![0x6a]).op_return_data(), None);
+        assert_eq!(
+            Script::new(b"\x6a\x01a\x01b".to_vec()).op_return_data(),
+            None
+        );
+        assert_eq!(Script::new_p2wpkh(&[0; 20]).op_return_data(), None);
+    }
+
+    #[test]
+    fn test_jsonl_writer() {
+        use rust_week_3_exercises::jsonl::{self, CoreTransaction};
+        use serde_json::{Value, json};
+
+        let input = TransactionInput::new(OutPoint::new([0x11; 32], 2), Script::new(vec![]), 7)
+            .with_witness(Witness::new(vec![vec![0xab; 2]]));
+        let tx = BitcoinTransaction::new(
+            2,
+            vec![input],
+            vec![TransactionOutput::new(
+                150_000_000,
+                Script::new_p2tr(&[0x33; 32]),
+            )],
+            0,
+        );
+
+        let mut out = Vec::new();
+        let lines = jsonl::write_jsonl(&mut out, [CoreTransaction(&tx), CoreTransaction(&tx)]);
+        assert_eq!(lines.unwrap(), 2);
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.ends_with('\n'));
+        assert_eq!(text.lines().count(), 2);
+
+        let line: Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(line["version"], 2);
+        assert_eq!(line["locktime"], 0);
+        assert_eq!(tx.total_size(), tx.to_bytes().len());
+        assert_eq!(tx.base_size(), tx.to_bytes_legacy().len());
+        assert_eq!(line["size"], tx.total_size());
+        assert_eq!(line["weight"], tx.base_size() * 3 + tx.total_size());
+        assert_eq!(
+            line["vin"],
+            json!([{
+                "txid": "11".repeat(32),
+                "vout": 2,
+                "scriptSig": {"asm": "", "hex": ""},
+                "txinwitness": ["abab"],
+                "sequence": 7,
+            }])
+        );
+        assert_eq!(line["vout"][0]["value"], 1.5);
+        assert_eq!(line["vout"][0]["n"], 0);
+        assert_eq!(
+            line["vout"][0]["scriptPubKey"]["type"],
+            "witness_v1_taproot"
+        );
+        assert_eq!(
+            line["vout"][0]["scriptPubKey"]["asm"],
+            format!("1 {}", "33".repeat(32))
+        );
+
+        // The genesis block matches `getblock <hash> 2`, less chain context.
+        // Its header and txid need sha2.
+        #[cfg(feature = "sha2")]
+        {
+            let genesis = Block::new(genesis_header(), vec![genesis_coinbase()]);
+            let mut writer = jsonl::JsonlWriter::new(Vec::new());
+            writer.write_block(&genesis).unwrap();
+            writer.write_transaction(&genesis.txdata[0]).unwrap();
+            assert_eq!(writer.lines(), 2);
+            let text = String::from_utf8(writer.into_inner()).unwrap();
+            let mut lines = text
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(line).unwrap());
+            let block = lines.next().unwrap();
+            let coinbase = lines.next().unwrap();
+
+            let merkle_root = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+            assert_eq!(block["merkleroot"], merkle_root);
+            assert_eq!(block["versionHex"], "00000001");
+            assert_eq!(block["bits"], "1d00ffff");
+            assert_eq!(block["nTx"], 1);
+            assert_eq!(block.get("previousblockhash"), None);
+            assert_eq!(
+                (&block["size"], &block["strippedsize"]),
+                (&json!(285), &json!(285))
+            );
+            assert_eq!(block["weight"], 1140);
+            assert_eq!(block["tx"][0], coinbase);
+
+            assert_eq!(coinbase["weight"], 816);
+            assert_eq!(coinbase["vin"][0]["sequence"], 4294967295u32);
+            assert!(
+                coinbase["vin"][0]["coinbase"]
+                    .as_str()
+                    .unwrap()
+                    .starts_with("04ffff001d")
+            );
+            assert_eq!(coinbase["vin"][0].get("txid"), None);
+            assert_eq!(coinbase["vout"][0]["value"], 50.0);
+            assert_eq!(coinbase["vout"][0]["scriptPubKey"]["type"], "pubkey");
+
+            assert_eq!(
+                block["hash"],
+                "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+            );
+            assert_eq!(coinbase["txid"], merkle_root);
+            assert_eq!(coinbase["hash"], merkle_root);
+        }
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_block_merkle_root_rejects_duplicated_tx() {
+        let txs: Vec<_> = (0..3)
+            .map(|i| {
+                let input =
+                    TransactionInput::new(OutPoint::new([i; 32], 0), Script::new(vec![]), 0);
+                BitcoinTransaction::new(1, vec![input], vec![], 0)
+            })
+            .collect();
+        let txids: Vec<_> = txs.iter().map(BitcoinTransaction::txid).collect();
+        let root = merkle::merkle_root(&txids).unwrap();
+        let header = BlockHeader::new(1, BlockHash([0; 32]), root, 0, 0, 0);
+        let block = Block::new(header, txs);
+        assert!(block.check_merkle_root());
+
+        // Appending a copy of the last transaction leaves the root unchanged.
+        let mut mutated = block.clone();
+        mutated.txdata.push(mutated.txdata[2].clone());
+        let mutated_txids: Vec<_> = mutated
+            .txdata
+            .iter()
+            .map(BitcoinTransaction::txid)
+            .collect();
+        assert_eq!(
+            merkle::merkle_root_mutated(&mutated_txids),
+            Some((root, true))
+        );
+        assert!(!mutated.check_merkle_root());
+        assert_eq!(merkle::merkle_root_mutated(&txids), Some((root, false)));
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_header_chain_hostile_time_delta() {
+        use headers::HeaderChain;
+
+        // Two headers: a full record, then one with SAME_VERSION | SAME_BITS
+        // and a zigzag time delta far outside u32 range.
+        for delta in [u64::MAX, u64::MAX - 1] {
+            let mut bytes = vec![0x02, 0x01];
+            bytes.extend_from_slice(&genesis_header().to_bytes());
+            bytes.push(0x06);
+            bytes.extend_from_slice(&CompactSize::new(delta).to_bytes());
+            bytes.extend_from_slice(&[0u8; 36]);
+            assert_eq!(
+                HeaderChain::from_bytes(&bytes),
+                Err(BitcoinError::InvalidFormat)
+            );
+        }
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_header_chain_decode_limits() {
+        use headers::HeaderChain;
+
+        let mut chain = HeaderChain::new();
+        let mut header = genesis_header();
+        for _ in 0..3 {
+            chain.push(header.clone()).unwrap();
+            header = BlockHeader::new(
+                1,
+                header.block_hash(),
+                [0; 32],
+                header.time + 600,
+                0x1d00ffff,
+                0,
+            );
+        }
+        let bytes = chain.to_bytes();
+        let decode = |limits: DecodeLimits| {
+            let mut reader = SliceReader::new(&bytes);
+            HeaderChain::consensus_decode_with_limits(&mut reader, &limits).map(|(chain, _)| chain)
+        };
+        let defaults = DecodeLimits::default();
+
+        assert_eq!(decode(defaults), Ok(chain));
+        assert_eq!(
+            decode(DecodeLimits {
+                max_items: 2,
+                ..defaults
+            }),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+        assert_eq!(
+            decode(DecodeLimits {
+                max_total_size: bytes.len() - 1,
+                ..defaults
+            }),
+            Err(BitcoinError::SizeLimitExceeded)
+        );
+    }
 }